@@ -1,11 +1,208 @@
-use serde_json::Value;
-use std::io;
-use crate::virsh;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::error::VirshError;
+use crate::utils;
+use crate::virsh::Virsh;
+
+/// Delay between guest-exec-status polls while a guest command is running.
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How guest-agent RPCs reach a guest. Every probe in this module goes
+/// through this trait, so a VM can be reached via `virsh qemu-agent-command`
+/// or directly over a QGA socket without the probes caring which.
+pub trait AgentTransport: Send + Sync {
+    /// Send one QGA command (a JSON string) to `vm` and return the full reply
+    /// object (`{"return": ...}`); agent-reported errors become `Err`.
+    fn agent_command(&self, vm: &str, payload: &str, timeout_secs: u64) -> Result<Value, VirshError>;
+}
+
+impl AgentTransport for Virsh {
+    fn agent_command(&self, vm: &str, payload: &str, timeout_secs: u64) -> Result<Value, VirshError> {
+        self.qemu_agent_command(vm, payload, timeout_secs)
+    }
+}
+
+/// Speaks the QGA JSON protocol directly over a guest agent chardev socket
+/// (e.g. QEMU `-chardev socket,path=/run/qga/web01.sock,server=on,wait=off`
+/// wired to `org.qemu.guest_agent.0`), without spawning virsh or needing
+/// libvirt. The socket serves one client at a time, so calls are serialized.
+#[derive(Debug)]
+pub struct UnixSocketAgent {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl UnixSocketAgent {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AgentTransport for UnixSocketAgent {
+    /// Connect, synchronise with `guest-sync` (discarding any stale replies a
+    /// previous client left in the channel), then send `payload` and read its
+    /// reply. `timeout_secs` bounds the whole exchange.
+    fn agent_command(&self, _vm: &str, payload: &str, timeout_secs: u64) -> Result<Value, VirshError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs.max(1));
+        let exchange = || -> io::Result<Value> {
+            let mut conn = QgaConn::connect(&self.path, deadline, timeout_secs)?;
+            let id = sync_id();
+            conn.send(&json!({ "execute": "guest-sync", "arguments": { "id": id } }).to_string())?;
+            while conn.recv(true)?.get("return").and_then(|v| v.as_u64()) != Some(id) {}
+            conn.send(payload)?;
+            conn.recv(false)
+        };
+        let reply = exchange().map_err(socket_error)?;
+        if let Some(err) = reply.get("error") {
+            let desc = err.get("desc").and_then(|v| v.as_str()).unwrap_or("unknown error");
+            let msg = format!("guest agent error: {}", desc);
+            return Err(match err.get("class").and_then(|v| v.as_str()) {
+                Some("CommandNotFound" | "CommandDisabled") => VirshError::AgentCommandUnsupported(msg),
+                _ => VirshError::Agent(msg),
+            });
+        }
+        Ok(reply)
+    }
+}
+
+/// Classify a failed socket exchange: nobody listening or the agent hanging
+/// up means it isn't connected; an unanswered read is a timeout.
+fn socket_error(e: io::Error) -> VirshError {
+    match e.kind() {
+        io::ErrorKind::TimedOut => VirshError::Timeout(e.to_string()),
+        io::ErrorKind::PermissionDenied => VirshError::PermissionDenied(e.to_string()),
+        io::ErrorKind::InvalidData => VirshError::Parse(e.to_string()),
+        _ => VirshError::AgentNotConnected(e.to_string()),
+    }
+}
+
+/// One connection to a QGA socket with a deadline for every read.
+struct QgaConn<'a> {
+    path: &'a Path,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    deadline: Instant,
+    timeout_secs: u64,
+}
+
+impl<'a> QgaConn<'a> {
+    fn connect(path: &'a Path, deadline: Instant, timeout_secs: u64) -> io::Result<Self> {
+        let writer = UnixStream::connect(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("guest agent socket {}: {}", path.display(), e)))?;
+        writer.set_write_timeout(Some(Duration::from_secs(timeout_secs.max(1))))?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self { path, reader, writer, deadline, timeout_secs })
+    }
+
+    fn send(&mut self, payload: &str) -> io::Result<()> {
+        self.writer.write_all(payload.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+
+    /// Read the next JSON reply line. While `syncing`, lines that aren't JSON
+    /// (leftovers of an interrupted exchange) are skipped instead of failing.
+    fn recv(&mut self, syncing: bool) -> io::Result<Value> {
+        loop {
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(self.timed_out());
+            }
+            self.reader.get_ref().set_read_timeout(Some(remaining))?;
+            let mut line = Vec::new();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "guest agent closed the socket")),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Err(self.timed_out());
+                }
+                Err(e) => return Err(e),
+            }
+            match serde_json::from_slice::<Value>(&line) {
+                Ok(v) => return Ok(v),
+                Err(_) if syncing || line.iter().all(u8::is_ascii_whitespace) => continue,
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("json parse: {}", e))),
+            }
+        }
+    }
+
+    fn timed_out(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("guest agent at {} did not answer within {}s", self.path.display(), self.timeout_secs),
+        )
+    }
+}
+
+/// A fresh id for `guest-sync`: unique within the process and unlikely to
+/// match a stale reply from another process's exchange.
+fn sync_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    (u64::from(nanos) << 16 | COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff) & 0x1f_ffff_ffff_ffff
+}
+
+/// Per-VM transport selection: VMs with a configured QGA socket are reached
+/// directly, everything else through `virsh qemu-agent-command`.
+pub struct AgentRouter {
+    virsh: Virsh,
+    sockets: HashMap<String, UnixSocketAgent>,
+}
+
+impl AgentRouter {
+    pub fn new(virsh: Virsh) -> Self {
+        Self { virsh, sockets: HashMap::new() }
+    }
+
+    /// Route agent calls for `vm` to the QGA socket at `path`.
+    pub fn add_socket(&mut self, vm: impl Into<String>, path: impl Into<PathBuf>) {
+        self.sockets.insert(vm.into(), UnixSocketAgent::new(path));
+    }
+
+    pub fn transport_for(&self, vm: &str) -> &dyn AgentTransport {
+        match self.sockets.get(vm) {
+            Some(sock) => sock,
+            None => &self.virsh,
+        }
+    }
+}
+
+impl AgentTransport for AgentRouter {
+    fn agent_command(&self, vm: &str, payload: &str, timeout_secs: u64) -> Result<Value, VirshError> {
+        self.transport_for(vm).agent_command(vm, payload, timeout_secs)
+    }
+}
+
+/// Ask the guest agent for its version and enabled commands (`guest-info`).
+/// Every agent since qemu-ga 0.15 answers this, so it is the probe to run
+/// before anything else.
+pub fn try_guest_info(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<utils::AgentInfo, VirshError> {
+    let payload = r#"{"execute":"guest-info"}"#;
+    let json: Value = agent.agent_command(vm, payload, timeout_secs)?;
+    match json.get("return") {
+        Some(ret) => Ok(utils::parse_guest_info(ret)),
+        None => Err(VirshError::Parse("guest-info: no return value".to_string())),
+    }
+}
 
 /// Try guest-get-osinfo and return a friendly OS string if present.
-pub fn try_guest_get_osinfo(vm: &str, timeout_secs: u64) -> io::Result<Option<String>> {
+pub fn try_guest_get_osinfo(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<Option<String>, VirshError> {
     let payload = r#"{"execute":"guest-get-osinfo"}"#;
-    let json: Value = virsh::virsh_qemu_agent(vm, payload, timeout_secs)?;
+    let json: Value = agent.agent_command(vm, payload, timeout_secs)?;
     if let Some(ret) = json.get("return") {
         if let Some(pretty_name) = ret.get("pretty-name").and_then(|v| v.as_str()) {
             return Ok(Some(pretty_name.to_string()));
@@ -24,9 +221,9 @@ pub fn try_guest_get_osinfo(vm: &str, timeout_secs: u64) -> io::Result<Option<St
 }
 
 /// Try guest-get-os (older RPC) — similar parsing strategy.
-pub fn try_guest_get_os(vm: &str, timeout_secs: u64) -> io::Result<Option<String>> {
+pub fn try_guest_get_os(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<Option<String>, VirshError> {
     let payload = r#"{"execute":"guest-get-os"}"#;
-    let json: Value = virsh::virsh_qemu_agent(vm, payload, timeout_secs)?;
+    let json: Value = agent.agent_command(vm, payload, timeout_secs)?;
     if let Some(ret) = json.get("return") {
         if let Some(pretty) = ret.get("pretty").and_then(|v| v.as_str()) {
             return Ok(Some(pretty.to_string()));
@@ -38,3 +235,91 @@ pub fn try_guest_get_os(vm: &str, timeout_secs: u64) -> io::Result<Option<String
     }
     Ok(None)
 }
+
+/// Ask the guest agent for its network interfaces (`guest-network-get-interfaces`).
+pub fn try_guest_network_interfaces(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<Vec<utils::GuestInterface>, VirshError> {
+    let payload = r#"{"execute":"guest-network-get-interfaces"}"#;
+    let json: Value = agent.agent_command(vm, payload, timeout_secs)?;
+    match json.get("return") {
+        Some(ret) => Ok(utils::parse_guest_interfaces(ret)),
+        None => Err(VirshError::Parse("guest-network-get-interfaces: no return value".to_string())),
+    }
+}
+
+/// Ask the guest agent for its mounted filesystems (`guest-get-fsinfo`).
+pub fn try_guest_fsinfo(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<Vec<utils::GuestFilesystem>, VirshError> {
+    let payload = r#"{"execute":"guest-get-fsinfo"}"#;
+    let json: Value = agent.agent_command(vm, payload, timeout_secs)?;
+    match json.get("return") {
+        Some(ret) => Ok(utils::parse_guest_fsinfo(ret)),
+        None => Err(VirshError::Parse("guest-get-fsinfo: no return value".to_string())),
+    }
+}
+
+/// Run `path args...` inside the guest with guest-exec, poll guest-exec-status
+/// until the process exits (or `timeout_secs` elapses) and return its decoded
+/// stdout. A non-zero exit code yields `Ok(None)`.
+pub fn guest_exec_capture(
+    agent: &dyn AgentTransport,
+    vm: &str,
+    path: &str,
+    args: &[&str],
+    timeout_secs: u64,
+) -> Result<Option<String>, VirshError> {
+    let payload = json!({
+        "execute": "guest-exec",
+        "arguments": { "path": path, "arg": args, "capture-output": true },
+    });
+    let json = agent.agent_command(vm, &payload.to_string(), timeout_secs)?;
+    let pid = json
+        .pointer("/return/pid")
+        .and_then(|v| v.as_i64())
+        .ok_or_else(|| VirshError::Parse(format!("guest-exec returned no pid: {}", json)))?;
+
+    let status_payload = json!({ "execute": "guest-exec-status", "arguments": { "pid": pid } }).to_string();
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    loop {
+        let status = agent.agent_command(vm, &status_payload, timeout_secs)?;
+        let ret = status.get("return").cloned().unwrap_or(Value::Null);
+        if ret.get("exited").and_then(|v| v.as_bool()) == Some(true) {
+            if ret.get("exitcode").and_then(|v| v.as_i64()).unwrap_or(0) != 0 {
+                return Ok(None);
+            }
+            let out = match ret.get("out-data").and_then(|v| v.as_str()) {
+                Some(b64) => STANDARD
+                    .decode(b64)
+                    .map_err(|e| VirshError::Parse(format!("guest-exec out-data: {}", e)))?,
+                None => Vec::new(),
+            };
+            return Ok(Some(String::from_utf8_lossy(&out).to_string()));
+        }
+        if Instant::now() >= deadline {
+            return Err(VirshError::Timeout(format!(
+                "guest-exec {} (pid {}) did not exit within {}s",
+                path, pid, timeout_secs
+            )));
+        }
+        thread::sleep(EXEC_POLL_INTERVAL);
+    }
+}
+
+/// Last-resort OS detection for agents without guest-get-osinfo: read
+/// /etc/os-release on Linux guests, otherwise ask Windows via `ver` and then
+/// `systeminfo`.
+pub fn try_guest_exec_os(agent: &dyn AgentTransport, vm: &str, timeout_secs: u64) -> Result<Option<String>, VirshError> {
+    if let Ok(Some(out)) = guest_exec_capture(agent, vm, "/bin/cat", &["/etc/os-release"], timeout_secs)
+        && let Some(name) = utils::parse_os_release(&out)
+    {
+        return Ok(Some(name));
+    }
+
+    if let Ok(Some(out)) = guest_exec_capture(agent, vm, "cmd.exe", &["/c", "ver"], timeout_secs) {
+        let ver = out.trim();
+        if !ver.is_empty() {
+            return Ok(Some(ver.to_string()));
+        }
+    }
+
+    let out = guest_exec_capture(agent, vm, "cmd.exe", &["/c", "systeminfo"], timeout_secs)?;
+    Ok(out.as_deref().and_then(utils::parse_systeminfo_os))
+}
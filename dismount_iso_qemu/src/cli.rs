@@ -1,45 +1,382 @@
-use std::sync::Arc;
+use std::path::PathBuf;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use crate::commands::{self, Command, RunOptions};
+use crate::domxml::{self, DomainXml, Firmware};
+use crate::media::{self, AttachedMedia, IsoSources};
 use crate::probe::ProbeManager;
-use std::io::{self, Write};
+use crate::{error, output, persist, scan};
 
-pub fn run(probe_mgr: Arc<ProbeManager>) -> anyhow::Result<()> {
+const COMMANDS: &[&str] = &["list", "refresh", "info", "os", "media", "df", "isos", "insert", "eject", "help", "exit", "quit"];
+
+const HELP: &str = "\
+Commands:
+  list                               VM table (cached probes)
+  refresh                            re-probe every VM and print the table
+  info <vm>                          status, definition summary and CD-ROM drives of one VM
+  os <vm>                            detected guest OS
+  media                              CD-ROM devices of all VMs with attached sources
+  df [vm] [--threshold PCT]          guest filesystem usage, flagging those over PCT% full
+  isos                               ISO images from the configured pool/directory
+  insert <vm> <iso> [target]         insert an ISO (default: first empty drive)
+  eject <vm|all> [--target T] [--live] [--config]
+                                     eject media (default: every loaded drive); `all`
+                                     lists the attached ISOs and asks which to eject
+  help                               this text
+  exit                               leave the shell";
+
+/// Tab-completion for the shell: command names, then VM names, then ISO
+/// paths (known images, else filesystem completion) for `insert`.
+struct ShellHelper {
+    vms: Vec<String>,
+    isos: Vec<String>,
+    files: FilenameCompleter,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let word = &before[start..];
+        let arg_idx = before[..start].split_whitespace().count();
+        let cmd = before.split_whitespace().next().unwrap_or("");
+
+        let matching = |items: &mut dyn Iterator<Item = &str>| -> Vec<Pair> {
+            items
+                .filter(|c| c.starts_with(word))
+                .map(|c| Pair { display: c.to_string(), replacement: c.to_string() })
+                .collect()
+        };
+
+        match (arg_idx, cmd) {
+            (0, _) => Ok((start, matching(&mut COMMANDS.iter().copied()))),
+            (1, "eject") => {
+                let mut names = self.vms.iter().map(String::as_str).chain(["all"]);
+                Ok((start, matching(&mut names)))
+            }
+            (_, "eject") => Ok((start, matching(&mut ["--target", "--live", "--config"].into_iter()))),
+            (1, "info" | "os" | "df" | "insert") => Ok((start, matching(&mut self.vms.iter().map(String::as_str)))),
+            (2, "insert") => {
+                let pairs = matching(&mut self.isos.iter().map(String::as_str));
+                if pairs.is_empty() {
+                    return self.files.complete(line, pos, ctx);
+                }
+                Ok((start, pairs))
+            }
+            _ => Ok((pos, Vec::new())),
+        }
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Interactive command shell with persistent history and tab-completion.
+pub fn run(probe_mgr: &ProbeManager, iso_sources: IsoSources, opts: &RunOptions) -> anyhow::Result<()> {
+    let virsh = probe_mgr.virsh();
+    let mut rl: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    rl.set_helper(Some(ShellHelper {
+        vms: virsh.list_vms().unwrap_or_default(),
+        isos: media::iso_candidates(virsh, &iso_sources),
+        files: FilenameCompleter::new(),
+    }));
+    let history = history_path();
+    let _ = rl.load_history(&history);
+
+    println!("Type 'help' for commands, Tab to complete.");
     loop {
-        println!("1) Mount ISO");
-        println!("2) Scan mounted ISOs");
-        println!("3) Exit");
-        print!("Select option: ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        match input.trim() {
-            "1" => {
-                println!("Mount ISO not implemented in this snippet.");
-            }
-            "2" => {
-                match crate::virsh::list_vms() {
-                    Ok(vms) => {
-                        if vms.is_empty() {
-                            println!("No VMs found (virsh returned no names).");
-                        } else {
-                            println!("{:20} {}", "VM", "OS");
-                            for vm in vms {
-                                match probe_mgr.get_os(&vm) {
-                                    Ok(Some(os)) => println!("{:20} {}", vm, os),
-                                    Ok(None) => println!("{:20} (unknown)", vm),
-                                    Err(e) => println!("{:20} error: {}", vm, e),
-                                }
-                            }
-                        }
-                    }
+        let line = match rl.readline("iso> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let args = split_args(&line);
+        let Some(cmd) = args.first() else { continue };
+        let _ = rl.add_history_entry(line.as_str());
+
+        match (cmd.as_str(), &args[1..]) {
+            ("exit" | "quit", _) => break,
+            ("help", _) => println!("{}", HELP),
+            ("list", []) => {
+                commands::run(Command::List, probe_mgr, opts);
+            }
+            ("refresh", []) => {
+                let vms = match scan::list_vms(probe_mgr, opts.state) {
+                    Ok(vms) => vms,
                     Err(e) => {
                         println!("Failed to list VMs via virsh: {}", e);
+                        if let Some(hint) = error::hint(&e) {
+                            println!("Hint: {}", hint);
+                        }
+                        continue;
                     }
+                };
+                scan::parallel_map(&vms, opts.workers, |vm| {
+                    let _ = probe_mgr.refresh_os(vm);
+                });
+                let rows = scan::scan_vms_with_cpu(probe_mgr, &vms, opts.workers);
+                output::render(&rows, opts.format, &mut std::io::stdout().lock())?;
+                if let Some(h) = rl.helper_mut() {
+                    h.vms = vms;
+                    h.isos = media::iso_candidates(virsh, &iso_sources);
+                }
+            }
+            ("info", [vm]) => info(probe_mgr, vm, opts)?,
+            ("os", [vm]) => {
+                commands::run(Command::Os { vm: vm.clone() }, probe_mgr, opts);
+            }
+            ("media", []) => {
+                commands::run(Command::Media, probe_mgr, opts);
+            }
+            ("df", rest) => {
+                let (vm, threshold) = match df_args(rest) {
+                    Some(parsed) => parsed,
+                    None => {
+                        println!("Usage: df [vm] [--threshold PCT]");
+                        continue;
+                    }
+                };
+                commands::run(Command::Df { vm, threshold }, probe_mgr, opts);
+            }
+            ("isos", []) => {
+                let isos = media::iso_candidates(virsh, &iso_sources);
+                if isos.is_empty() {
+                    println!("No ISO images found (set ISO_POOL or ISO_DIR).");
+                }
+                for iso in &isos {
+                    println!("{}", iso);
+                }
+                if let Some(h) = rl.helper_mut() {
+                    h.isos = isos;
                 }
             }
-            "3" => break,
-            _ => println!("Unknown option"),
+            ("insert", [vm, iso, rest @ ..]) if rest.len() <= 1 => {
+                let target = rest.first().cloned();
+                commands::run(Command::Insert { vm: vm.clone(), iso: iso.clone(), target }, probe_mgr, opts);
+            }
+            ("eject", [vm, rest @ ..]) => {
+                let Some((target, live, config)) = eject_args(rest) else {
+                    println!("Usage: eject <vm|all> [--target T] [--live] [--config]");
+                    continue;
+                };
+                if vm == "all" {
+                    eject_all(&mut rl, probe_mgr, opts, target, live, config)?;
+                } else {
+                    commands::run(Command::Eject { vm: vm.clone(), target, live, config }, probe_mgr, opts);
+                }
+            }
+            _ => println!("Unknown command or wrong arguments: {} (try 'help')", line.trim()),
         }
     }
+
+    if let Some(parent) = history.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = rl.save_history(&history) {
+        eprintln!("Warning: failed to save history {}: {}", history.display(), e);
+    }
     Ok(())
 }
+
+/// `eject all`: list the ISOs attached to any VM (only on `target`, if
+/// given), then eject all of them or the numbered selection the user
+/// answers with. Anything else, including Ctrl-C, ejects nothing.
+fn eject_all(
+    rl: &mut Editor<ShellHelper, DefaultHistory>,
+    probe_mgr: &ProbeManager,
+    opts: &RunOptions,
+    target: Option<String>,
+    live: bool,
+    config: bool,
+) -> anyhow::Result<()> {
+    let virsh = probe_mgr.virsh();
+    let vms = virsh.list_vms().unwrap_or_default();
+    let attached: Vec<AttachedMedia> = media::attached_media(virsh, &vms)
+        .into_iter()
+        .filter(|m| target.as_deref().is_none_or(|t| m.target == t))
+        .collect();
+    if attached.is_empty() {
+        println!("No ISO media attached to any VM.");
+        return Ok(());
+    }
+    for (i, m) in attached.iter().enumerate() {
+        println!("  {:>2}) {:20} {:8} {}", i + 1, m.vm, m.target, m.source);
+    }
+    let answer = match rl.readline("Eject these? [y/N, or numbers like 1 3] ") {
+        Ok(answer) => answer,
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    match parse_selection(&answer, attached.len()) {
+        Some(selected) if selected.is_empty() => println!("Nothing ejected."),
+        Some(selected) => {
+            for m in selected.into_iter().map(|i| &attached[i]) {
+                let eject = Command::Eject { vm: m.vm.clone(), target: Some(m.target.clone()), live, config };
+                commands::run(eject, probe_mgr, opts);
+            }
+        }
+        None => println!("Invalid selection {:?}; nothing ejected.", answer.trim()),
+    }
+    Ok(())
+}
+
+/// Parse the answer to a numbered list of `count` items: `y`/`yes` selects
+/// all, 1-based numbers separated by spaces or commas pick items, and an
+/// empty answer or `n`/`no` selects none. `None` if a number is out of range
+/// or not a number.
+pub fn parse_selection(answer: &str, count: usize) -> Option<Vec<usize>> {
+    let answer = answer.trim().to_ascii_lowercase();
+    match answer.as_str() {
+        "y" | "yes" => return Some((0..count).collect()),
+        "" | "n" | "no" => return Some(Vec::new()),
+        _ => {}
+    }
+    let mut picked = Vec::new();
+    for token in answer.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()) {
+        let n: usize = token.parse().ok().filter(|n| (1..=count).contains(n))?;
+        if !picked.contains(&(n - 1)) {
+            picked.push(n - 1);
+        }
+    }
+    Some(picked)
+}
+
+/// `info <vm>`: the status row for one VM, a summary of its definition and
+/// its CD-ROM drives.
+fn info(probe_mgr: &ProbeManager, vm: &str, opts: &RunOptions) -> anyhow::Result<()> {
+    let rows = scan::scan_vms(probe_mgr, &[vm.to_string()], 1, false);
+    output::render(&rows, opts.format, &mut std::io::stdout().lock())?;
+    match domxml::fetch(probe_mgr.virsh(), vm) {
+        Ok(dom) => println!("{}", definition_summary(&dom)),
+        Err(e) => println!("Failed to read the domain XML of {}: {}", vm, e),
+    }
+    match media::cdrom_devices(probe_mgr.virsh(), vm) {
+        Ok(devs) if devs.is_empty() => println!("No CD-ROM devices."),
+        Ok(devs) => {
+            for dev in devs {
+                println!("  {:8} {}", dev.target, dev.source.as_deref().unwrap_or("(empty)"));
+            }
+        }
+        Err(e) => println!("Failed to list block devices of {}: {}", vm, e),
+    }
+    Ok(())
+}
+
+/// One line describing a domain definition for `info`, e.g.
+/// `Definition: 4 vCPUs, 8.0 GiB, pc-q35-8.2, UEFI (secure boot), 2 disks, 1 NIC`.
+fn definition_summary(dom: &DomainXml) -> String {
+    let mut parts = Vec::new();
+    match (dom.vcpus, dom.current_vcpus) {
+        (Some(max), Some(cur)) if cur != max => parts.push(format!("{} of {} vCPUs", cur, max)),
+        (Some(max), _) => parts.push(format!("{} vCPUs", max)),
+        _ => {}
+    }
+    if let Some(mem) = dom.memory {
+        parts.push(mem.to_string());
+    }
+    if let Some(machine) = &dom.machine {
+        parts.push(machine.clone());
+    }
+    parts.push(match dom.firmware {
+        Firmware::Bios => "BIOS".to_string(),
+        Firmware::Efi { secure_boot: true, .. } => "UEFI (secure boot)".to_string(),
+        Firmware::Efi { .. } => "UEFI".to_string(),
+    });
+    let disks = dom.disks.iter().filter(|d| d.device == "disk").count();
+    parts.push(format!("{} disk{}", disks, if disks == 1 { "" } else { "s" }));
+    parts.push(format!("{} NIC{}", dom.interfaces.len(), if dom.interfaces.len() == 1 { "" } else { "s" }));
+    if !dom.agent_channel {
+        parts.push("no guest agent channel".to_string());
+    }
+    format!("Definition: {}", parts.join(", "))
+}
+
+/// Parse `df` arguments: an optional VM name and `--threshold PCT` (0-100).
+fn df_args(args: &[String]) -> Option<(Option<String>, Option<u8>)> {
+    let mut vm = None;
+    let mut threshold = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--threshold" {
+            threshold = Some(iter.next()?.parse::<u8>().ok().filter(|&t| t <= 100)?);
+        } else if vm.is_none() && !arg.starts_with("--") {
+            vm = Some(arg.clone());
+        } else {
+            return None;
+        }
+    }
+    Some((vm, threshold))
+}
+
+/// Parse `eject` arguments after the VM the way the `eject` subcommand takes
+/// them: `--target <dev>`, `--live` and `--config`. Anything else is a usage
+/// error.
+pub fn eject_args(args: &[String]) -> Option<(Option<String>, bool, bool)> {
+    let (mut target, mut live, mut config) = (None, false, false);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--target" if target.is_none() => target = Some(iter.next()?.clone()),
+            "--live" => live = true,
+            "--config" => config = true,
+            _ => return None,
+        }
+    }
+    Some((target, live, config))
+}
+
+/// History lives next to the probe cache; falls back to the working directory.
+fn history_path() -> PathBuf {
+    persist::state_dir()
+        .map(|dir| dir.join("iso_tool_history"))
+        .unwrap_or_else(|| PathBuf::from(".iso_tool_history"))
+}
+
+/// Split a command line on whitespace, keeping single- or double-quoted
+/// sections (e.g. ISO paths with spaces) together.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut cur = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => cur.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    args.push(std::mem::take(&mut cur));
+                    in_word = false;
+                }
+            }
+            None => {
+                cur.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        args.push(cur);
+    }
+    args
+}
@@ -0,0 +1,269 @@
+// src/commands.rs
+use clap::{Parser, Subcommand};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::error;
+use crate::fsinfo;
+use crate::media;
+use crate::metrics;
+use crate::output::{self, Format};
+use crate::probe::ProbeManager;
+use crate::scan::{self, StateFilter};
+use crate::utils::BlockDevice;
+use crate::virsh::MediaScope;
+use crate::watch;
+
+/// Exit code: success.
+pub const EXIT_OK: i32 = 0;
+/// Exit code: a virsh call or media operation failed. (Usage errors exit with
+/// clap's code 2.)
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code: the VM has no matching CD-ROM, or the OS could not be detected.
+pub const EXIT_NOT_FOUND: i32 = 3;
+
+/// Enumerate libvirt/QEMU VMs, probe guests and manage ISO media.
+/// Without a subcommand, prints the VM table and starts the interactive shell.
+#[derive(Debug, Parser)]
+#[command(name = "dismount_iso_qemu", version)]
+pub struct Cli {
+    /// libvirt connection URI
+    #[arg(short = 'c', long, env = "LIBVIRT_URI", default_value = "qemu:///system", global = true)]
+    pub uri: String,
+
+    /// Output format for the VM status table
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    pub format: Format,
+
+    /// Only show domains in this power state
+    #[arg(long, value_enum, global = true)]
+    pub state: Option<StateFilter>,
+
+    /// Reach VM's guest agent directly over this QGA unix socket instead of
+    /// via virsh (repeatable)
+    #[arg(long = "agent-socket", value_name = "VM=PATH", value_parser = parse_agent_socket, global = true)]
+    pub agent_sockets: Vec<(String, PathBuf)>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the VM table (OS, memory, CPU time)
+    List,
+    /// Print every CD-ROM device with its bus and attached source
+    Media,
+    /// Eject media from a VM's CD-ROM drives
+    Eject {
+        /// Domain name
+        vm: String,
+        /// Only eject this target (e.g. sda); default is every loaded drive
+        #[arg(long)]
+        target: Option<String>,
+        /// Apply to the running domain
+        #[arg(long)]
+        live: bool,
+        /// Apply to the persistent definition
+        #[arg(long)]
+        config: bool,
+    },
+    /// Insert an ISO into a VM's CD-ROM drive
+    Insert {
+        /// Domain name
+        vm: String,
+        /// ISO path as seen by the libvirt host
+        iso: String,
+        /// CD-ROM target (e.g. sda); default is the first empty drive
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Print the detected guest OS of a VM
+    Os {
+        /// Domain name
+        vm: String,
+    },
+    /// Print guest filesystems with used/total space (guest agent `guest-get-fsinfo`)
+    Df {
+        /// Only this domain; default is every running domain (or those matching --state)
+        vm: Option<String>,
+        /// Flag filesystems more than PCT percent full
+        #[arg(long, value_name = "PCT", value_parser = clap::value_parser!(u8).range(0..=100))]
+        threshold: Option<u8>,
+    },
+    /// Export per-VM Prometheus metrics over HTTP at /metrics
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9177")]
+        listen: String,
+        /// Seconds between refreshes
+        #[arg(long, default_value_t = 15)]
+        interval: u64,
+    },
+    /// Redraw the VM table in place every few seconds, with CPU/memory deltas
+    Watch {
+        /// Seconds between refreshes
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+    },
+}
+
+/// Settings shared by every subcommand.
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    /// Upper bound on concurrent VM probes.
+    pub workers: usize,
+    pub format: Format,
+    /// Restricts `list`, `media`, `df`, `watch` and `serve` to matching domains.
+    pub state: Option<StateFilter>,
+}
+
+/// Run one non-interactive subcommand and return its process exit code.
+pub fn run(command: Command, probe_mgr: &ProbeManager, opts: &RunOptions) -> i32 {
+    let virsh = probe_mgr.virsh();
+    match command {
+        Command::List => {
+            let vms = match scan::list_vms(probe_mgr, opts.state) {
+                Ok(vms) => vms,
+                Err(e) => return fail(e),
+            };
+            let rows = scan::scan_vms_with_cpu(probe_mgr, &vms, opts.workers);
+            match output::render(&rows, opts.format, &mut io::stdout().lock()) {
+                Ok(()) => EXIT_OK,
+                Err(e) => fail(e),
+            }
+        }
+        Command::Media => {
+            let vms = match scan::list_vms(probe_mgr, opts.state) {
+                Ok(vms) => vms,
+                Err(e) => return fail(e),
+            };
+            media::print_media_table(&media::scan_media(virsh, &vms));
+            EXIT_OK
+        }
+        Command::Eject { vm, target, live, config } => {
+            let scope = match (live, config) {
+                (true, true) => MediaScope::Both,
+                (true, false) => MediaScope::Live,
+                (false, true) => MediaScope::Config,
+                (false, false) => MediaScope::Current,
+            };
+            let cdroms = match media::cdrom_devices(virsh, &vm) {
+                Ok(devs) => devs,
+                Err(e) => return fail(e),
+            };
+            let loaded: Vec<&BlockDevice> = cdroms
+                .iter()
+                .filter(|d| d.source.is_some())
+                .filter(|d| target.as_deref().is_none_or(|t| d.target == t))
+                .collect();
+            if let Some(t) = &target
+                && !cdroms.iter().any(|d| &d.target == t)
+            {
+                eprintln!("{} has no CD-ROM target {}", vm, t);
+                return EXIT_NOT_FOUND;
+            }
+            if loaded.is_empty() {
+                println!("No media attached to {}.", vm);
+                return EXIT_OK;
+            }
+            let mut code = EXIT_OK;
+            for dev in loaded {
+                match virsh.change_media_eject(&vm, &dev.target, scope) {
+                    Ok(()) => println!("Ejected {} from {} on {}.", dev.source.as_deref().unwrap_or(""), dev.target, vm),
+                    Err(e) => code = fail(e),
+                }
+            }
+            code
+        }
+        Command::Insert { vm, iso, target } => {
+            let cdroms = match media::cdrom_devices(virsh, &vm) {
+                Ok(devs) => devs,
+                Err(e) => return fail(e),
+            };
+            let Some(dev) = insert_target(&cdroms, target.as_deref()) else {
+                match target {
+                    Some(t) => eprintln!("{} has no CD-ROM target {}", vm, t),
+                    None if cdroms.is_empty() => eprintln!("{} has no CD-ROM devices", vm),
+                    None => eprintln!("{} has no empty CD-ROM drive; pass --target to replace media", vm),
+                }
+                return EXIT_NOT_FOUND;
+            };
+            match virsh.change_media_insert(&vm, &dev.target, &iso, dev.source.is_some()) {
+                Ok(()) => {
+                    println!("Inserted {} into {} on {}.", iso, dev.target, vm);
+                    EXIT_OK
+                }
+                Err(e) => fail(e),
+            }
+        }
+        Command::Os { vm } => match probe_mgr.get_os(&vm) {
+            Ok(Some(os)) => {
+                println!("{}", os);
+                EXIT_OK
+            }
+            Ok(None) => {
+                eprintln!("Could not detect the OS of {}", vm);
+                EXIT_NOT_FOUND
+            }
+            Err(e) => fail(e),
+        },
+        Command::Df { vm, threshold } => {
+            let vms = match vm {
+                Some(vm) => vec![vm],
+                None => match scan::list_vms(probe_mgr, opts.state.or(Some(StateFilter::Running))) {
+                    Ok(vms) => vms,
+                    Err(e) => return fail(e),
+                },
+            };
+            let rows = fsinfo::scan_filesystems(probe_mgr, &vms, opts.workers);
+            match fsinfo::print_fs_table(&rows, threshold, &mut io::stdout().lock()) {
+                Ok(()) => EXIT_OK,
+                Err(e) => fail(e),
+            }
+        }
+        Command::Serve { listen, interval } => {
+            match metrics::serve(probe_mgr, &listen, Duration::from_secs(interval), opts.workers, opts.state) {
+                Ok(()) => EXIT_OK,
+                Err(e) => fail(e),
+            }
+        }
+        Command::Watch { interval } => {
+            let interval = Duration::from_secs(interval);
+            match watch::watch(probe_mgr, interval, opts.workers, opts.state, &mut io::stdout().lock()) {
+                Ok(()) => EXIT_OK,
+                Err(e) => fail(e),
+            }
+        }
+    }
+}
+
+/// Resolve the drive for `insert`: the named target, else the first empty
+/// drive, else the only drive if there is exactly one.
+fn insert_target<'a>(cdroms: &'a [BlockDevice], target: Option<&str>) -> Option<&'a BlockDevice> {
+    match target {
+        Some(t) => cdroms.iter().find(|d| d.target == t),
+        None => cdroms
+            .iter()
+            .find(|d| d.source.is_none())
+            .or(if cdroms.len() == 1 { cdroms.first() } else { None }),
+    }
+}
+
+fn parse_agent_socket(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((vm, path)) if !vm.is_empty() && !path.is_empty() => Ok((vm.to_string(), PathBuf::from(path))),
+        _ => Err(format!("expected VM=PATH, got `{}`", s)),
+    }
+}
+
+/// Report `e` on stderr, with a hint when the cause is a classified virsh or
+/// agent error, and return the failure exit code.
+fn fail(e: impl Into<anyhow::Error>) -> i32 {
+    let e = e.into();
+    eprintln!("Error: {}", e);
+    if let Some(hint) = error::hint(&e) {
+        eprintln!("Hint: {}", hint);
+    }
+    EXIT_FAILURE
+}
@@ -0,0 +1,199 @@
+// src/domxml.rs
+use roxmltree::Node;
+use crate::error::VirshError;
+use crate::utils::Bytes;
+use crate::virsh::Virsh;
+
+/// Name of the virtio-serial channel the QEMU guest agent listens on.
+pub const AGENT_CHANNEL: &str = "org.qemu.guest_agent.0";
+
+/// A domain definition from `virsh dumpxml`, reduced to the parts the media,
+/// inventory and validation views share. Elements missing from the XML are
+/// `None`/empty rather than errors, since libvirt omits defaults freely.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainXml {
+    pub name: String,
+    pub uuid: Option<String>,
+    /// Hypervisor type from `<domain type=...>` (`kvm`, `qemu`).
+    pub domain_type: String,
+    /// `<memory>`: the maximum the guest can balloon up to.
+    pub memory: Option<Bytes>,
+    /// `<currentMemory>`: the balloon target at boot.
+    pub current_memory: Option<Bytes>,
+    /// `<vcpu>`: the maximum vCPU count.
+    pub vcpus: Option<u32>,
+    /// `<vcpu current=...>`, when fewer vCPUs than the maximum are online.
+    pub current_vcpus: Option<u32>,
+    pub arch: Option<String>,
+    /// Machine type, e.g. `pc-q35-8.2` or `pc-i440fx-7.2`.
+    pub machine: Option<String>,
+    pub firmware: Firmware,
+    pub disks: Vec<Disk>,
+    pub interfaces: Vec<Interface>,
+    /// The domain has an `org.qemu.guest_agent.0` channel for the agent.
+    pub agent_channel: bool,
+}
+
+/// Boot firmware of a domain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Firmware {
+    #[default]
+    Bios,
+    Efi {
+        /// `<loader>` path (the OVMF code image), if spelled out.
+        loader: Option<String>,
+        secure_boot: bool,
+    },
+}
+
+/// A `<disk>` element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Disk {
+    /// `disk`, `cdrom`, `floppy` or `lun`.
+    pub device: String,
+    /// Backing kind from `<disk type=...>`: `file`, `block`, `volume`, `network`.
+    pub disk_type: String,
+    /// Image format from `<driver type=...>` (`qcow2`, `raw`).
+    pub format: Option<String>,
+    /// `<target dev=...>`, e.g. `vda` or `sda`.
+    pub target: String,
+    /// `<target bus=...>`, `(unknown)` if absent.
+    pub bus: String,
+    /// File, device, `pool/volume` or network image name; `None` for an empty tray.
+    pub source: Option<String>,
+    pub readonly: bool,
+}
+
+/// An `<interface>` element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interface {
+    /// `network`, `bridge`, `direct`, `user`, ...
+    pub iface_type: String,
+    pub mac: Option<String>,
+    /// The libvirt network, host bridge or device the NIC is attached to.
+    pub source: Option<String>,
+    /// Host-side tap device (`vnet2`) while the domain runs.
+    pub target: Option<String>,
+    /// NIC model (`virtio`, `e1000e`).
+    pub model: Option<String>,
+}
+
+impl DomainXml {
+    /// The CD-ROM drives, with or without media.
+    pub fn cdroms(&self) -> impl Iterator<Item = &Disk> {
+        self.disks.iter().filter(|d| d.device == "cdrom")
+    }
+}
+
+/// Fetch `virsh dumpxml <vm>` and parse it. A definition that isn't valid XML
+/// is reported as `VirshError::Parse`.
+pub fn fetch(virsh: &Virsh, vm: &str) -> Result<DomainXml, VirshError> {
+    let xml = virsh.dumpxml_raw(vm)?;
+    parse(&xml).map_err(|e| VirshError::Parse(format!("domain XML of {}: {}", vm, e)))
+}
+
+/// Parse a libvirt domain XML definition.
+pub fn parse(xml: &str) -> Result<DomainXml, roxmltree::Error> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let domain = doc.root_element();
+    let os = child(domain, "os");
+    let os_type = os.and_then(|os| child(os, "type"));
+    let vcpu = child(domain, "vcpu");
+    let devices: Vec<Node> = child(domain, "devices").map(|d| d.children().collect()).unwrap_or_default();
+
+    Ok(DomainXml {
+        name: child_text(domain, "name").unwrap_or_default(),
+        uuid: child_text(domain, "uuid"),
+        domain_type: domain.attribute("type").unwrap_or("").to_string(),
+        memory: child(domain, "memory").and_then(quantity),
+        current_memory: child(domain, "currentMemory").and_then(quantity),
+        vcpus: vcpu.and_then(|v| v.text()).and_then(|t| t.trim().parse().ok()),
+        current_vcpus: vcpu.and_then(|v| v.attribute("current")).and_then(|c| c.parse().ok()),
+        arch: os_type.and_then(|t| t.attribute("arch")).map(str::to_string),
+        machine: os_type.and_then(|t| t.attribute("machine")).map(str::to_string),
+        firmware: os.map(firmware).unwrap_or_default(),
+        disks: devices.iter().filter(|n| n.has_tag_name("disk")).filter_map(|n| disk(*n)).collect(),
+        interfaces: devices.iter().filter(|n| n.has_tag_name("interface")).map(|n| interface(*n)).collect(),
+        agent_channel: devices.iter().filter(|n| n.has_tag_name("channel")).any(|n| {
+            child(*n, "target").and_then(|t| t.attribute("name")) == Some(AGENT_CHANNEL)
+        }),
+    })
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, tag: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(tag))
+}
+
+fn child_text(node: Node, tag: &str) -> Option<String> {
+    child(node, tag).and_then(|n| n.text()).map(|t| t.trim().to_string())
+}
+
+/// A `<memory unit=...>` style element. libvirt's default unit is KiB;
+/// single-letter and `*iB` units are binary (`k`, `MiB`, ...), while `KB`,
+/// `MB`, `GB` and `TB` are decimal.
+fn quantity(node: Node) -> Option<Bytes> {
+    let n: u64 = node.text()?.trim().parse().ok()?;
+    let unit = node.attribute("unit").unwrap_or("KiB").to_ascii_lowercase();
+    let scale: u64 = match unit.as_str() {
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        other => return Bytes::parse(&format!("{} {}", n, other)),
+    };
+    n.checked_mul(scale).map(Bytes)
+}
+
+/// EFI if `<os firmware='efi'>` or a pflash `<loader>` is present; secure
+/// boot from the loader's `secure` flag or the `secure-boot` firmware feature.
+fn firmware(os: Node) -> Firmware {
+    let loader = child(os, "loader");
+    let efi = os.attribute("firmware") == Some("efi") || loader.and_then(|l| l.attribute("type")) == Some("pflash");
+    if !efi {
+        return Firmware::Bios;
+    }
+    let secure_feature = child(os, "firmware").is_some_and(|fw| {
+        fw.children()
+            .any(|f| f.attribute("name") == Some("secure-boot") && f.attribute("enabled") == Some("yes"))
+    });
+    Firmware::Efi {
+        loader: loader.and_then(|l| l.text()).map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+        secure_boot: secure_feature || loader.and_then(|l| l.attribute("secure")) == Some("yes"),
+    }
+}
+
+fn disk(node: Node) -> Option<Disk> {
+    let target = child(node, "target")?;
+    let source = child(node, "source").and_then(|src| {
+        match (src.attribute("pool"), src.attribute("volume")) {
+            (Some(pool), Some(volume)) => Some(format!("{}/{}", pool, volume)),
+            _ => src.attribute("file").or(src.attribute("dev")).or(src.attribute("name")).map(str::to_string),
+        }
+    });
+    Some(Disk {
+        device: node.attribute("device").unwrap_or("disk").to_string(),
+        disk_type: node.attribute("type").unwrap_or("").to_string(),
+        format: child(node, "driver").and_then(|d| d.attribute("type")).map(str::to_string),
+        target: target.attribute("dev").unwrap_or("").to_string(),
+        bus: target.attribute("bus").unwrap_or("(unknown)").to_string(),
+        source,
+        readonly: child(node, "readonly").is_some(),
+    })
+}
+
+fn interface(node: Node) -> Interface {
+    let source = child(node, "source").and_then(|src| {
+        src.attribute("network").or(src.attribute("bridge")).or(src.attribute("dev")).map(str::to_string)
+    });
+    Interface {
+        iface_type: node.attribute("type").unwrap_or("").to_string(),
+        mac: child(node, "mac").and_then(|m| m.attribute("address")).map(str::to_string),
+        source,
+        target: child(node, "target").and_then(|t| t.attribute("dev")).map(str::to_string),
+        model: child(node, "model").and_then(|m| m.attribute("type")).map(str::to_string),
+    }
+}
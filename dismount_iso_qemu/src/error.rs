@@ -0,0 +1,140 @@
+// src/error.rs
+use std::fmt;
+use std::io;
+
+/// Why a virsh call or guest-agent RPC failed, classified from virsh's stderr
+/// (which `SystemRunner` forces into English) or from the socket error of a
+/// direct agent connection, so callers can react to the cause instead of
+/// matching on message text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VirshError {
+    /// The `virsh` binary could not be found.
+    NotInstalled,
+    /// `virsh` could not be spawned for another reason.
+    Spawn(String),
+    /// libvirt refused the connection or the operation (polkit, file
+    /// permissions, SSH authentication).
+    PermissionDenied(String),
+    /// No libvirt daemon answered at the connection URI.
+    ConnectionFailed(String),
+    DomainNotFound(String),
+    DomainNotRunning(String),
+    /// The domain has no guest agent channel, or nothing is listening on it.
+    AgentNotConnected(String),
+    /// The guest agent doesn't know the command or has it disabled.
+    AgentCommandUnsupported(String),
+    /// The guest agent answered with an error of its own.
+    Agent(String),
+    /// virsh or the guest agent did not answer in time.
+    Timeout(String),
+    /// Output that couldn't be parsed (e.g. malformed agent JSON).
+    Parse(String),
+    /// Any other failure of `virsh <command>`, with its stderr.
+    Failed { command: String, stderr: String },
+}
+
+impl VirshError {
+    /// Classify a failed `virsh <command>` by its stderr. The guest-agent
+    /// classes only apply to `qemu-agent-command`; other subcommands use
+    /// phrases like "command not found" for unrelated failures.
+    pub fn from_stderr(command: &str, stderr: &str) -> Self {
+        let msg = stderr.trim().trim_start_matches("error: ").to_string();
+        let lower = stderr.to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| lower.contains(n));
+        let agent = command == "qemu-agent-command";
+
+        if agent && has(&["guest agent is not connected", "guest agent is not responding", "guest agent not available"]) {
+            VirshError::AgentNotConnected(msg)
+        } else if agent && has(&["has not been found", "has been disabled", "command not found"]) {
+            VirshError::AgentCommandUnsupported(msg)
+        } else if has(&["domain not found", "failed to get domain"]) {
+            VirshError::DomainNotFound(msg)
+        } else if has(&["domain is not running", "domain is not active"]) {
+            VirshError::DomainNotRunning(msg)
+        } else if has(&["timed out", "timeout expired"]) {
+            VirshError::Timeout(msg)
+        } else if has(&["permission denied", "authentication failed", "access denied", "not authorized"]) {
+            VirshError::PermissionDenied(msg)
+        } else if has(&["failed to connect to the hypervisor", "no connection driver"]) {
+            VirshError::ConnectionFailed(msg)
+        } else if agent && lower.contains("unable to execute qemu agent command") {
+            VirshError::Agent(msg)
+        } else {
+            VirshError::Failed { command: command.to_string(), stderr: msg }
+        }
+    }
+
+    /// Classify the failure to spawn `virsh` itself.
+    pub fn from_spawn(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => VirshError::NotInstalled,
+            io::ErrorKind::PermissionDenied => VirshError::PermissionDenied(e.to_string()),
+            _ => VirshError::Spawn(e.to_string()),
+        }
+    }
+
+    /// Errors that mean no guest-agent RPC to this domain can succeed right
+    /// now, so trying further probes is pointless.
+    pub fn agent_unreachable(&self) -> bool {
+        matches!(
+            self,
+            VirshError::NotInstalled
+                | VirshError::Spawn(_)
+                | VirshError::PermissionDenied(_)
+                | VirshError::ConnectionFailed(_)
+                | VirshError::DomainNotFound(_)
+                | VirshError::DomainNotRunning(_)
+                | VirshError::AgentNotConnected(_)
+                | VirshError::Timeout(_)
+        )
+    }
+
+    /// What the user can do about it, where there is something to say.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            VirshError::NotInstalled => Some("install the libvirt client tools (virsh) and make sure they are on PATH"),
+            VirshError::PermissionDenied(_) => Some(
+                "run as root or add your user to the libvirt group; for qemu+ssh URIs check the SSH key",
+            ),
+            VirshError::ConnectionFailed(_) => Some("is libvirtd running? check -c/--uri or LIBVIRT_URI"),
+            VirshError::DomainNotFound(_) => Some("check the domain name with `dismount_iso_qemu list`"),
+            VirshError::DomainNotRunning(_) => Some("start the domain first (virsh start <vm>)"),
+            VirshError::AgentNotConnected(_) => Some(
+                "install and start qemu-guest-agent in the guest and give the domain an org.qemu.guest_agent.0 channel",
+            ),
+            VirshError::AgentCommandUnsupported(_) => {
+                Some("the guest agent is too old for this command or blocks it (--block-rpcs)")
+            }
+            VirshError::Timeout(_) => Some("the guest or libvirt is busy or hung; try again later"),
+            VirshError::Spawn(_) | VirshError::Agent(_) | VirshError::Parse(_) | VirshError::Failed { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for VirshError {
+    /// Classified virsh failures print virsh's own message, which already
+    /// names the cause.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VirshError::NotInstalled => write!(f, "virsh is not installed"),
+            VirshError::Spawn(msg) => write!(f, "failed to run virsh: {}", msg),
+            VirshError::Failed { command, stderr } => write!(f, "virsh {} failed: {}", command, stderr),
+            VirshError::PermissionDenied(msg)
+            | VirshError::ConnectionFailed(msg)
+            | VirshError::DomainNotFound(msg)
+            | VirshError::DomainNotRunning(msg)
+            | VirshError::AgentNotConnected(msg)
+            | VirshError::AgentCommandUnsupported(msg)
+            | VirshError::Agent(msg)
+            | VirshError::Timeout(msg)
+            | VirshError::Parse(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VirshError {}
+
+/// The hint for `e` if it is (or wraps) a `VirshError`.
+pub fn hint(e: &anyhow::Error) -> Option<&'static str> {
+    e.downcast_ref::<VirshError>().and_then(VirshError::hint)
+}
@@ -0,0 +1,94 @@
+// src/fsinfo.rs
+use std::io::{self, Write};
+use crate::output::truncate;
+use crate::probe::ProbeManager;
+use crate::scan;
+use crate::utils::GuestFilesystem;
+
+const NAME_WIDTH: usize = 20;
+const MOUNT_WIDTH: usize = 24;
+const TYPE_WIDTH: usize = 8;
+const SIZE_WIDTH: usize = 10;
+
+/// The mounted filesystems of one VM for the disk usage report, or why they
+/// couldn't be read.
+#[derive(Debug, Clone)]
+pub struct VmFilesystems {
+    pub vm: String,
+    pub filesystems: Vec<GuestFilesystem>,
+    pub error: Option<String>,
+}
+
+impl VmFilesystems {
+    /// Filesystems more than `threshold` percent full.
+    pub fn over(&self, threshold: u8) -> impl Iterator<Item = &GuestFilesystem> {
+        self.filesystems
+            .iter()
+            .filter(move |fs| fs.used_percent().is_some_and(|p| p > f64::from(threshold)))
+    }
+}
+
+/// Ask every VM's guest agent for its filesystems on a pool of `workers`
+/// threads, through `ProbeManager` so repeated reports reuse the cache.
+/// Guests known not to be running are skipped without an agent call.
+pub fn scan_filesystems(mgr: &ProbeManager, vms: &[String], workers: usize) -> Vec<VmFilesystems> {
+    scan::parallel_map(vms, workers, |vm| {
+        let result = match mgr.domain_state(vm) {
+            Some(state) if !state.is_running() => Err(format!("domain is {}", state)),
+            _ => match mgr.get_filesystems(vm) {
+                Ok(Some(filesystems)) => Ok(filesystems),
+                Ok(None) => Err("guest agent unavailable (cached)".to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+        };
+        match result {
+            Ok(filesystems) => VmFilesystems { vm: vm.clone(), filesystems, error: None },
+            Err(e) => VmFilesystems { vm: vm.clone(), filesystems: Vec::new(), error: Some(e) },
+        }
+    })
+}
+
+/// Print the disk usage table. With a `threshold`, filesystems above it are
+/// flagged and a summary line names the guests that have any.
+pub fn print_fs_table(rows: &[VmFilesystems], threshold: Option<u8>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:NAME_WIDTH$} {:MOUNT_WIDTH$} {:TYPE_WIDTH$} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$} {:>5}",
+        "VM", "Mount", "Type", "Used", "Total", "Use%"
+    )?;
+    for row in rows {
+        if let Some(e) = &row.error {
+            writeln!(out, "{:NAME_WIDTH$} ({})", truncate(&row.vm, NAME_WIDTH), e)?;
+            continue;
+        }
+        if row.filesystems.is_empty() {
+            writeln!(out, "{:NAME_WIDTH$} (no filesystems reported)", truncate(&row.vm, NAME_WIDTH))?;
+        }
+        for fs in &row.filesystems {
+            let pct = fs.used_percent();
+            let full = threshold.is_some_and(|t| pct.is_some_and(|p| p > f64::from(t)));
+            writeln!(
+                out,
+                "{:NAME_WIDTH$} {:MOUNT_WIDTH$} {:TYPE_WIDTH$} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$} {:>5}{}",
+                truncate(&row.vm, NAME_WIDTH),
+                truncate(&fs.mountpoint, MOUNT_WIDTH),
+                truncate(&fs.fs_type, TYPE_WIDTH),
+                fs.used.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string()),
+                fs.total.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string()),
+                pct.map(|p| format!("{:.0}%", p)).unwrap_or_else(|| "-".to_string()),
+                if full { "  [FULL]" } else { "" }
+            )?;
+        }
+    }
+
+    if let Some(t) = threshold {
+        let full: Vec<&str> = rows.iter().filter(|r| r.over(t).next().is_some()).map(|r| r.vm.as_str()).collect();
+        if full.is_empty() {
+            writeln!(out, "\nNo guest is above {}% full.", t)?;
+        } else {
+            writeln!(out, "\n{} guest(s) above {}% full: {}", full.len(), t, full.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
@@ -0,0 +1,21 @@
+//! Library side of `dismount_iso_qemu`: the virsh client and command runner,
+//! guest-agent probes, media management and formatting helpers used by the
+//! binary, exposed so integration tests can drive them with recorded outputs.
+
+pub mod agent;
+pub mod cli;
+pub mod commands;
+pub mod domxml;
+pub mod error;
+pub mod fsinfo;
+pub mod media;
+pub mod metrics;
+pub mod output;
+pub mod persist;
+pub mod probe;
+pub mod runner;
+pub mod scan;
+pub mod stats;
+pub mod utils;
+pub mod virsh;
+pub mod watch;
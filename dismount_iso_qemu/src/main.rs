@@ -1,75 +1,105 @@
-mod cli;
-mod virsh;
-mod agent;
-mod probe;
-mod utils;
-
+use std::path::Path;
 use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
-use probe::ProbeManager;
+use clap::Parser;
+use dismount_iso_qemu::{cli, commands, error, media, output, persist, scan};
+use dismount_iso_qemu::commands::{Cli, RunOptions};
+use dismount_iso_qemu::persist::PersistedCache;
+use dismount_iso_qemu::probe::{CacheTtls, ProbeManager};
+
+/// Upper bound on concurrent VM probes during the startup scan.
+const SCAN_WORKERS: usize = 8;
 
-/// Entry point: perform an initial synchronous VM scan (OS, memory, CPU time),
-/// print a human-friendly table, then enter the interactive CLI.
+/// Entry point: run a subcommand if one was given; otherwise perform an
+/// initial parallel VM scan (OS, memory, CPU time), print a human-friendly
+/// table, then enter the interactive shell.
 fn main() -> anyhow::Result<()> {
-    let libvirt_uri = std::env::var("LIBVIRT_URI").unwrap_or_else(|_| "qemu:///system".into());
+    let args = Cli::parse();
     let timeout = Duration::from_secs(5);
-    let cache_ttl = Duration::from_secs(60);
+    let cache_ttls = CacheTtls {
+        os: Duration::from_secs(60),
+        dominfo: Duration::from_secs(5),
+        net: Duration::from_secs(30),
+        fs: Duration::from_secs(60),
+        negative: Duration::from_secs(15),
+    };
+    let iso_sources = media::IsoSources {
+        pool: std::env::var("ISO_POOL").ok(),
+        dir: Some(std::env::var("ISO_DIR").unwrap_or_else(|_| "/var/lib/libvirt/images".into()).into()),
+    };
+
+    let mut probe_mgr = ProbeManager::new(args.uri, timeout, cache_ttls)?;
+    for (vm, path) in args.agent_sockets {
+        probe_mgr = probe_mgr.with_agent_socket(vm, path);
+    }
+    let probe_mgr = Arc::new(probe_mgr);
+
+    let opts = RunOptions { workers: SCAN_WORKERS, format: args.format, state: args.state };
 
-    let probe_mgr = Arc::new(ProbeManager::new(libvirt_uri, timeout, cache_ttl)?);
+    // Non-interactive subcommand: run it and exit with its status code.
+    if let Some(command) = args.command {
+        std::process::exit(commands::run(command, &probe_mgr, &opts));
+    }
 
-    // --- STARTUP SCAN: enumerate VMs and print OS / memory / CPU table ---
-    match crate::virsh::list_vms() {
+    // --- STARTUP SCAN: probe all VMs in parallel, then print OS / memory / CPU table ---
+    // OS strings persisted by the previous run are shown immediately (if the
+    // domain UUID still matches) and refreshed in the background when stale,
+    // along with the agent info and interfaces the table skipped.
+    let cache_path = persist::default_cache_path();
+    let persisted = cache_path.as_deref().map(PersistedCache::load).unwrap_or_default();
+    match scan::list_vms(&probe_mgr, opts.state) {
         Ok(vms) => {
             if vms.is_empty() {
                 println!("No VMs found (virsh returned no names).\n");
             } else {
-                println!("{:20} {:40} {:24} {}", "VM", "OS", "Memory (used/max)", "CPU time");
-                for vm in vms {
-                    // OS probe (cached by ProbeManager)
-                    let os = match probe_mgr.get_os(&vm) {
-                        Ok(Some(s)) => s,
-                        Ok(None) => "(unknown)".to_string(),
-                        Err(e) => format!("error: {}", e),
-                    };
+                // Only VMs with a persisted entry need their UUID before the
+                // table; the rest are looked up in the background for saving.
+                let known: Vec<String> = vms.iter().filter(|vm| persisted.entries.contains_key(*vm)).cloned().collect();
+                scan::parallel_map(&known, SCAN_WORKERS, |vm| {
+                    let _ = probe_mgr.domain_uuid(vm);
+                });
+                let stale = probe_mgr.restore_os(&persisted);
 
-                    // dominfo probe (raw virsh output -> parsed DomInfo)
-                    let dominfo = match crate::virsh::dominfo_raw(&vm) {
-                        Ok(raw) => crate::utils::parse_dominfo(&raw),
-                        Err(_) => crate::utils::DomInfo { max_memory_mb: None, used_memory_mb: None, cpu_time: None },
-                    };
+                let rows = scan::scan_vms(&probe_mgr, &vms, SCAN_WORKERS, true);
+                output::render(&rows, args.format, &mut std::io::stdout().lock())?;
+                println!(); // blank line before the prompt
+                save_probe_cache(&probe_mgr, cache_path.as_deref());
 
-                    // Memory formatting: parse_dominfo returns numeric tokens (treat as KiB)
-                    let mem_used = crate::utils::format_memory_kib(dominfo.used_memory_mb);
-                    let mem_max = crate::utils::format_memory_kib(dominfo.max_memory_mb);
-                    let mem = if mem_used != "(unknown)" && mem_max != "(unknown)" {
-                        format!("{} / {}", mem_used, mem_max)
-                    } else if mem_used != "(unknown)" {
-                        mem_used
-                    } else if mem_max != "(unknown)" {
-                        mem_max
-                    } else {
-                        "(unknown)".to_string()
-                    };
-
-                    // CPU time: try to parse into seconds and pretty-print; fallback to raw string
-                    let cpu = dominfo.cpu_time
-                        .as_deref()
-                        .and_then(|s| crate::utils::parse_cpu_time_to_seconds(s))
-                        .map(|secs| crate::utils::format_seconds_dhms(secs))
-                        .unwrap_or_else(|| dominfo.cpu_time.clone().unwrap_or_else(|| "(unknown)".to_string()));
-
-                    println!("{:20} {:40} {:24} {}", vm, os, mem, cpu);
-                }
-                println!(); // blank line before menu
+                let mgr = Arc::clone(&probe_mgr);
+                let path = cache_path.clone();
+                thread::spawn(move || {
+                    scan::refresh_stale(&mgr, &vms, &stale, SCAN_WORKERS);
+                    scan::parallel_map(&vms, SCAN_WORKERS, |vm| {
+                        let _ = mgr.domain_uuid(vm);
+                    });
+                    save_probe_cache(&mgr, path.as_deref());
+                });
             }
         }
         Err(e) => {
-            eprintln!("Warning: failed to list VMs on startup: {}", e);
+            eprintln!("Warning: failed to list VMs on {} at startup: {}", probe_mgr.virsh().uri(), e);
+            if let Some(hint) = error::hint(&e) {
+                eprintln!("Hint: {}", hint);
+            }
         }
     }
     // --- END STARTUP SCAN ---
 
-    // Enter interactive CLI (blocking)
-    cli::run(probe_mgr)?;
+    // Enter interactive shell (blocking)
+    cli::run(&probe_mgr, iso_sources, &opts)?;
+    save_probe_cache(&probe_mgr, cache_path.as_deref());
     Ok(())
 }
+
+/// Persist the OS cache so the next run can print the table without probing.
+/// The snapshot is merged into the file's entries, so domains this run didn't
+/// look at keep theirs.
+fn save_probe_cache(probe_mgr: &ProbeManager, path: Option<&Path>) {
+    let Some(path) = path else { return };
+    let mut cache = PersistedCache::load(path);
+    cache.merge(probe_mgr.snapshot_os());
+    if let Err(e) = cache.save(path) {
+        eprintln!("Warning: failed to write probe cache {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,139 @@
+// src/media.rs
+use std::path::{Path, PathBuf};
+use crate::domxml;
+use crate::error::VirshError;
+use crate::utils::{self, BlockDevice};
+use crate::virsh::Virsh;
+
+/// Where the Mount ISO action looks for images: an optional libvirt storage
+/// pool and/or a local directory scanned for `*.iso` files.
+#[derive(Debug, Clone, Default)]
+pub struct IsoSources {
+    pub pool: Option<String>,
+    pub dir: Option<PathBuf>,
+}
+
+/// Return the CD-ROM devices of a VM (with or without media inserted).
+pub fn cdrom_devices(virsh: &Virsh, vm: &str) -> Result<Vec<BlockDevice>, VirshError> {
+    let raw = virsh.domblklist_raw(vm)?;
+    Ok(utils::parse_domblklist(&raw)
+        .into_iter()
+        .filter(|d| d.device == "cdrom")
+        .collect())
+}
+
+/// Collect candidate ISO paths from the configured pool and directory.
+/// A failing source is reported as a warning and skipped so the other one can
+/// still offer images. The result is sorted and de-duplicated.
+pub fn iso_candidates(virsh: &Virsh, sources: &IsoSources) -> Vec<String> {
+    let mut isos = Vec::new();
+
+    if let Some(pool) = &sources.pool {
+        match virsh.vol_list_raw(pool) {
+            Ok(raw) => isos.extend(utils::parse_vol_list_paths(&raw).into_iter().filter(|p| is_iso(p))),
+            Err(e) => eprintln!("Warning: failed to list pool '{}': {}", pool, e),
+        }
+    }
+
+    if let Some(dir) = &sources.dir {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.is_file() && is_iso(&path.to_string_lossy()) {
+                        isos.push(path.to_string_lossy().to_string());
+                    }
+                }
+            }
+            Err(e) => eprintln!("Warning: failed to read ISO directory {}: {}", dir.display(), e),
+        }
+    }
+
+    isos.sort();
+    isos.dedup();
+    isos
+}
+
+fn is_iso(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".iso")
+}
+
+/// A CD-ROM device that currently holds media.
+#[derive(Debug, Clone)]
+pub struct AttachedMedia {
+    pub vm: String,
+    pub target: String,
+    pub source: String,
+}
+
+/// Walk every VM and return the CD-ROM devices with media inserted. VMs whose
+/// block devices can't be listed are reported and skipped.
+pub fn attached_media(virsh: &Virsh, vms: &[String]) -> Vec<AttachedMedia> {
+    let mut attached = Vec::new();
+    for vm in vms {
+        match cdrom_devices(virsh, vm) {
+            Ok(devs) => {
+                for dev in devs {
+                    if let Some(source) = dev.source {
+                        attached.push(AttachedMedia { vm: vm.clone(), target: dev.target, source });
+                    }
+                }
+            }
+            Err(e) => eprintln!("Warning: failed to list block devices of {}: {}", vm, e),
+        }
+    }
+    attached
+}
+
+/// One CD-ROM row of the "Scan mounted ISOs" report.
+#[derive(Debug, Clone)]
+pub struct MediaRow {
+    pub vm: String,
+    pub target: String,
+    pub bus: String,
+    pub source: Option<String>,
+    /// The source is a file path that no longer exists. Only checked for local
+    /// connections; paths on a remote libvirt host can't be verified from here.
+    pub missing: bool,
+}
+
+/// Read each VM's domain XML and report its CD-ROM devices with bus and
+/// backing file. VMs whose definition can't be fetched or parsed are reported
+/// and skipped.
+pub fn scan_media(virsh: &Virsh, vms: &[String]) -> Vec<MediaRow> {
+    let mut rows = Vec::new();
+    for vm in vms {
+        let dom = match domxml::fetch(virsh, vm) {
+            Ok(dom) => dom,
+            Err(e) => {
+                eprintln!("Warning: failed to read domain XML of {}: {}", vm, e);
+                continue;
+            }
+        };
+        for disk in dom.cdroms() {
+            let missing = virsh.is_local()
+                && disk
+                    .source
+                    .as_deref()
+                    .is_some_and(|src| src.starts_with('/') && !Path::new(src).exists());
+            rows.push(MediaRow {
+                vm: vm.clone(),
+                target: disk.target.clone(),
+                bus: disk.bus.clone(),
+                source: disk.source.clone(),
+                missing,
+            });
+        }
+    }
+    rows
+}
+
+/// Print the "Scan mounted ISOs" table.
+pub fn print_media_table(rows: &[MediaRow]) {
+    println!("{:20} {:8} {:8} Source", "VM", "Target", "Bus");
+    for row in rows {
+        let source = row.source.as_deref().unwrap_or("(empty)");
+        let flag = if row.missing { "  [MISSING]" } else { "" };
+        println!("{:20} {:8} {:8} {}{}", row.vm, row.target, row.bus, source, flag);
+    }
+}
@@ -0,0 +1,167 @@
+// src/metrics.rs
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use crate::media;
+use crate::probe::ProbeManager;
+use crate::scan::{self, StateFilter, VmStatus};
+use crate::stats::DomStats;
+
+/// One VM's sample for the exporter: the scan row, its loaded CD-ROM count
+/// (`None` if `domblklist` failed) and the domstats record when the bulk
+/// query is available.
+#[derive(Debug, Clone)]
+pub struct VmMetrics {
+    pub status: VmStatus,
+    pub attached_isos: Option<usize>,
+    pub stats: Option<DomStats>,
+}
+
+/// Probe every VM through `ProbeManager`, so the cache TTLs bound how often the
+/// guest agents and `virsh dominfo` are actually hit.
+pub fn collect(mgr: &ProbeManager, workers: usize, filter: Option<StateFilter>) -> anyhow::Result<Vec<VmMetrics>> {
+    let vms = scan::list_vms(mgr, filter)?;
+    let rows = scan::scan_vms(mgr, &vms, workers, false);
+    let counts = scan::parallel_map(&vms, workers, |vm| {
+        media::cdrom_devices(mgr.virsh(), vm)
+            .ok()
+            .map(|devs| devs.iter().filter(|d| d.source.is_some()).count())
+    });
+    Ok(rows
+        .into_iter()
+        .zip(counts)
+        .map(|(status, attached_isos)| {
+            let stats = mgr.get_domstats(&status.name);
+            VmMetrics { status, attached_isos, stats }
+        })
+        .collect())
+}
+
+/// Render samples in the Prometheus text exposition format. Cumulative
+/// totals (CPU time, traffic) are counters, the rest gauges. Metrics a VM
+/// couldn't report (e.g. CPU time of a shut-off guest) are left out rather
+/// than exported as 0.
+pub fn render(vms: &[VmMetrics]) -> String {
+    type Getter = fn(&VmMetrics) -> Option<f64>;
+    let metrics: [(&str, &str, &str, Getter); 10] = [
+        ("memory_used_bytes", "gauge", "Memory currently used by the domain.", |m| {
+            m.status.memory_used_bytes.map(|b| b.0 as f64)
+        }),
+        ("memory_max_bytes", "gauge", "Maximum memory of the domain.", |m| {
+            m.status.memory_max_bytes.map(|b| b.0 as f64)
+        }),
+        ("cpu_seconds_total", "counter", "CPU time consumed by the domain.", |m| {
+            m.status.cpu_time_seconds.map(|s| s as f64)
+        }),
+        ("agent_up", "gauge", "1 if the guest agent answered guest-info.", |m| {
+            Some(if m.status.agent_version.is_some() { 1.0 } else { 0.0 })
+        }),
+        ("running", "gauge", "1 if the domain is running.", |m| {
+            m.status.state.map(|s| if s.is_running() { 1.0 } else { 0.0 })
+        }),
+        ("attached_isos", "gauge", "CD-ROM drives with media attached.", |m| m.attached_isos.map(|n| n as f64)),
+        ("net_rx_bytes_total", "counter", "Bytes received on all interfaces (domstats).", |m| {
+            m.stats.as_ref().map(|s| s.net.iter().map(|n| n.rx_bytes).sum::<u64>() as f64)
+        }),
+        ("net_tx_bytes_total", "counter", "Bytes sent on all interfaces (domstats).", |m| {
+            m.stats.as_ref().map(|s| s.net.iter().map(|n| n.tx_bytes).sum::<u64>() as f64)
+        }),
+        ("block_read_bytes_total", "counter", "Bytes read from all disks (domstats).", |m| {
+            m.stats.as_ref().map(|s| s.block.iter().map(|b| b.rd_bytes).sum::<u64>() as f64)
+        }),
+        ("block_write_bytes_total", "counter", "Bytes written to all disks (domstats).", |m| {
+            m.stats.as_ref().map(|s| s.block.iter().map(|b| b.wr_bytes).sum::<u64>() as f64)
+        }),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, get) in metrics {
+        let _ = writeln!(out, "# HELP dismount_iso_qemu_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE dismount_iso_qemu_{} {}", name, kind);
+        for m in vms {
+            if let Some(v) = get(m) {
+                let _ = writeln!(out, "dismount_iso_qemu_{}{{vm=\"{}\"}} {}", name, escape_label(&m.status.name), v);
+            }
+        }
+    }
+    out
+}
+
+/// Escape a label value per the exposition format (`\`, `"` and newline).
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `/metrics` on `listen` until the process is killed. A background
+/// thread re-collects every `interval`; scrapes return the last collection, so
+/// a slow guest never makes Prometheus time out.
+pub fn serve(
+    mgr: &ProbeManager,
+    listen: &str,
+    interval: Duration,
+    workers: usize,
+    filter: Option<StateFilter>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+    let body = RwLock::new(String::new());
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            loop {
+                match collect(mgr, workers, filter) {
+                    Ok(vms) => *body.write().unwrap() = render(&vms),
+                    Err(e) => eprintln!("Warning: metrics refresh failed: {}", e),
+                }
+                thread::sleep(interval);
+            }
+        });
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Warning: accept failed: {}", e);
+                    continue;
+                }
+            };
+            let snapshot = body.read().unwrap().clone();
+            if let Err(e) = handle(stream, &snapshot) {
+                eprintln!("Warning: metrics request failed: {}", e);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Answer one HTTP/1.x request: `GET /metrics` gets the rendered body,
+/// anything else a 404.
+fn handle(mut stream: TcpStream, body: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request has no body we care about.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = match (request_line.split_whitespace().next(), path) {
+        (Some("GET"), "/metrics") => ("200 OK", "text/plain; version=0.0.4", body),
+        _ => ("404 Not Found", "text/plain", "not found\n"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
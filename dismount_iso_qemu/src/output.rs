@@ -0,0 +1,154 @@
+// src/output.rs
+use std::io::{self, Write};
+use clap::ValueEnum;
+use crate::scan::VmStatus;
+use crate::utils;
+
+/// How the VM status table is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned, human-readable columns
+    #[default]
+    Table,
+    /// A JSON array of VM status records
+    Json,
+    /// CSV with a header row (raw bytes / seconds / percent values)
+    Csv,
+}
+
+const NAME_WIDTH: usize = 20;
+const STATE_WIDTH: usize = 11;
+const OS_WIDTH: usize = 40;
+const AGENT_WIDTH: usize = 8;
+const IP_WIDTH: usize = 20;
+const MAC_WIDTH: usize = 17;
+const MEM_WIDTH: usize = 24;
+const CPU_WIDTH: usize = 16;
+
+/// Write `rows` to `out` in the requested format.
+pub fn render(rows: &[VmStatus], format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Table => render_table(rows, out),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows).map_err(io::Error::other)?;
+            writeln!(out)
+        }
+        Format::Csv => render_csv(rows, out),
+    }
+}
+
+fn render_table(rows: &[VmStatus], out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:NAME_WIDTH$} {:STATE_WIDTH$} {:OS_WIDTH$} {:AGENT_WIDTH$} {:IP_WIDTH$} {:MAC_WIDTH$} {:MEM_WIDTH$} {:CPU_WIDTH$} {:>5} {:>6}",
+        "VM", "State", "OS", "Agent", "IP", "MAC", "Memory (used/max)", "CPU time", "vCPUs", "CPU %"
+    )?;
+    for row in rows {
+        let os = match (&row.os, &row.os_error) {
+            (Some(os), _) => os.clone(),
+            (None, Some(e)) => format!("error: {}", e),
+            (None, None) => "(unknown)".to_string(),
+        };
+
+        let mem = match (row.memory_used_bytes, row.memory_max_bytes) {
+            (Some(used), Some(max)) => format!("{} / {}", used, max),
+            (Some(b), None) | (None, Some(b)) => b.to_string(),
+            (None, None) => "(unknown)".to_string(),
+        };
+
+        // CPU time: pretty-print parsed seconds; fall back to the raw string
+        let cpu = row
+            .cpu_time_seconds
+            .map(utils::format_seconds_dhms)
+            .or_else(|| row.cpu_time_raw.clone())
+            .unwrap_or_else(|| "(unknown)".to_string());
+
+        let (ip, mac) = primary_address(row);
+        let state = row.state.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string());
+        let agent = row.agent_version.as_deref().unwrap_or("-");
+        let vcpus = row.vcpus.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string());
+        let pct = row.cpu_percent.map(|p| format!("{:.1}%", p)).unwrap_or_else(|| "-".to_string());
+
+        writeln!(
+            out,
+            "{:NAME_WIDTH$} {:STATE_WIDTH$} {:OS_WIDTH$} {:AGENT_WIDTH$} {:IP_WIDTH$} {:MAC_WIDTH$} {:MEM_WIDTH$} {:CPU_WIDTH$} {:>5} {:>6}",
+            truncate(&row.name, NAME_WIDTH),
+            state,
+            truncate(&os, OS_WIDTH),
+            truncate(agent, AGENT_WIDTH),
+            truncate(&ip, IP_WIDTH),
+            mac,
+            mem,
+            cpu,
+            vcpus,
+            pct
+        )?;
+    }
+    Ok(())
+}
+
+fn render_csv(rows: &[VmStatus], out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "name,state,os,agent_version,ip_addresses,macs,memory_used_bytes,memory_max_bytes,cpu_time_seconds,vcpus,cpu_percent"
+    )?;
+    for row in rows {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&row.name),
+            row.state.map(utils::DomainState::key).unwrap_or_default(),
+            csv_field(row.os.as_deref().unwrap_or("")),
+            csv_field(row.agent_version.as_deref().unwrap_or("")),
+            row.interfaces.iter().flat_map(|i| &i.addresses).cloned().collect::<Vec<_>>().join(" "),
+            row.interfaces.iter().filter_map(|i| i.mac.clone()).collect::<Vec<_>>().join(" "),
+            opt(row.memory_used_bytes.map(|b| b.0)),
+            opt(row.memory_max_bytes.map(|b| b.0)),
+            opt(row.cpu_time_seconds),
+            opt(row.vcpus),
+            row.cpu_percent.map(|p| format!("{:.1}", p)).unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+/// The address shown in the table: the first IPv4 address (else the first of
+/// any kind) with its interface's MAC, plus a `+N` count of the others.
+fn primary_address(row: &VmStatus) -> (String, String) {
+    let all: Vec<(&str, Option<&str>)> = row
+        .interfaces
+        .iter()
+        .flat_map(|i| i.addresses.iter().map(move |a| (a.as_str(), i.mac.as_deref())))
+        .collect();
+    let Some(&(ip, mac)) = all.iter().find(|(a, _)| !a.contains(':')).or(all.first()) else {
+        return ("-".to_string(), "-".to_string());
+    };
+    let ip = match all.len() {
+        1 => ip.to_string(),
+        n => format!("{} +{}", ip, n - 1),
+    };
+    (ip, mac.unwrap_or("-").to_string())
+}
+
+/// Shorten `s` to `width` characters, marking the cut with an ellipsis.
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    let mut cut: String = s.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or newline.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn opt(v: Option<impl ToString>) -> String {
+    v.map(|n| n.to_string()).unwrap_or_default()
+}
@@ -0,0 +1,83 @@
+// src/persist.rs
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+/// One VM's OS probe as stored on disk. `uuid` ties the entry to a specific
+/// domain so a VM re-created under the same name doesn't inherit a stale OS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedEntry {
+    pub uuid: String,
+    pub os: String,
+    /// Seconds since the Unix epoch when the OS was probed.
+    pub probed_at: u64,
+}
+
+impl PersistedEntry {
+    pub fn probed_at_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.probed_at)
+    }
+}
+
+/// Probe cache persisted across runs, keyed by VM name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedCache {
+    pub entries: BTreeMap<String, PersistedEntry>,
+}
+
+impl PersistedCache {
+    /// Load the cache from `path`. A missing or unreadable file yields an empty
+    /// cache: the worst case is simply re-probing every guest.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Add or replace the entries of `newer`, keeping the rest. A run that
+    /// only looked at some domains (e.g. `--state running`) must not drop the
+    /// others' entries.
+    pub fn merge(&mut self, newer: PersistedCache) {
+        self.entries.extend(newer.entries);
+    }
+
+    /// Write the cache to `path`, creating parent directories. The file is
+    /// written to a sibling temp file and renamed so readers never see a partial
+    /// file; saves from the background refresh and from exit are serialized.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        static SAVE_LOCK: Mutex<()> = Mutex::new(());
+        let _guard = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Per-user state directory: `$XDG_STATE_HOME/dismount_iso_qemu`, falling back
+/// to `~/.local/state/dismount_iso_qemu`.
+pub fn state_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("dismount_iso_qemu"))
+}
+
+/// Default location of the persisted probe cache.
+pub fn default_cache_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("probe_cache.json"))
+}
+
+/// Convert a wall-clock time to whole seconds since the epoch (0 if before it).
+pub fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
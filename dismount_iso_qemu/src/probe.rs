@@ -1,56 +1,503 @@
 use std::collections::HashMap;
 use std::sync::Mutex;
-use std::time::{Duration, Instant};
-use crate::agent;
-use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use std::path::PathBuf;
+use crate::agent::{self, AgentRouter, AgentTransport};
+use crate::error::VirshError;
+use crate::persist::{self, PersistedCache, PersistedEntry};
+use crate::stats::{self, DomStats};
+use crate::utils::{self, AgentInfo, DomInfo, DomainState, GuestFilesystem, GuestInterface};
+use crate::virsh::Virsh;
+use anyhow::{Result, bail};
+
+/// Cache lifetimes for each probed metric. Negative results (agent unreachable,
+/// dominfo failing) are kept for the shorter `negative` TTL so repeated scans
+/// don't stall on the same dead guests but still notice when they come back.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtls {
+    pub os: Duration,
+    pub dominfo: Duration,
+    pub net: Duration,
+    pub fs: Duration,
+    pub negative: Duration,
+}
+
+impl Default for CacheTtls {
+    fn default() -> Self {
+        Self {
+            os: Duration::from_secs(60),
+            dominfo: Duration::from_secs(5),
+            net: Duration::from_secs(30),
+            fs: Duration::from_secs(60),
+            negative: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Per-metric cache keyed by VM name. `None` values record a failed probe and
+/// expire after the negative TTL instead of the metric's own TTL. Entries keep
+/// their wall-clock probe time so they can be persisted and restored.
+struct TtlCache<T> {
+    ttl: Duration,
+    negative_ttl: Duration,
+    entries: Mutex<HashMap<String, CacheEntry<T>>>,
+}
+
+struct CacheEntry<T> {
+    val: Option<T>,
+    expires: Instant,
+    probed_at: SystemTime,
+}
+
+impl<T: Clone> TtlCache<T> {
+    fn new(ttl: Duration, negative_ttl: Duration) -> Self {
+        Self { ttl, negative_ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// `Some(entry)` if a fresh entry exists (`entry` is `None` for a cached miss).
+    fn get(&self, vm: &str) -> Option<Option<T>> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(vm)?;
+        (Instant::now() < entry.expires).then(|| entry.val.clone())
+    }
+
+    /// The last successful value regardless of age.
+    fn peek(&self, vm: &str) -> Option<T> {
+        self.entries.lock().unwrap().get(vm).and_then(|e| e.val.clone())
+    }
+
+    fn put(&self, vm: &str, val: Option<T>) {
+        let ttl = if val.is_some() { self.ttl } else { self.negative_ttl };
+        let entry = CacheEntry { val, expires: Instant::now() + ttl, probed_at: SystemTime::now() };
+        self.entries.lock().unwrap().insert(vm.to_string(), entry);
+    }
+
+    /// Insert a value probed at `probed_at` (e.g. loaded from disk). It expires
+    /// when it would have had it been cached at that time, possibly immediately.
+    fn put_at(&self, vm: &str, val: T, probed_at: SystemTime) {
+        let age = SystemTime::now().duration_since(probed_at).unwrap_or(Duration::ZERO);
+        let expires = Instant::now() + self.ttl.saturating_sub(age);
+        let entry = CacheEntry { val: Some(val), expires, probed_at };
+        self.entries.lock().unwrap().insert(vm.to_string(), entry);
+    }
+
+    /// All successful values with their probe times.
+    fn positives(&self) -> Vec<(String, T, SystemTime)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(vm, e)| e.val.clone().map(|v| (vm.clone(), v, e.probed_at)))
+            .collect()
+    }
+}
+
+/// One uncached CPU-time reading of a domain, taken at `at`.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuSample {
+    pub at: Instant,
+    pub cpu_secs: f64,
+    pub vcpus: Option<u32>,
+}
+
+impl CpuSample {
+    /// CPU utilisation between `earlier` and this sample as a percentage of
+    /// the domain's vCPU capacity (100 = every vCPU busy). `None` without a
+    /// vCPU count or if no wall time passed.
+    pub fn percent_since(&self, earlier: &CpuSample) -> Option<f64> {
+        let vcpus = self.vcpus.filter(|&n| n > 0)?;
+        let wall = self.at.checked_duration_since(earlier.at)?.as_secs_f64();
+        if wall <= 0.0 {
+            return None;
+        }
+        let busy = (self.cpu_secs - earlier.cpu_secs).max(0.0);
+        Some(busy / wall / vcpus as f64 * 100.0)
+    }
+}
 
 /// Simple cached probe manager. Not production hardened — illustrative only.
 pub struct ProbeManager {
-    uri: String,
+    virsh: Virsh,
+    agent: AgentRouter,
     timeout_secs: u64,
-    cache_ttl: Duration,
-    cache: Mutex<HashMap<String, (String, Instant)>>,
+    os_cache: TtlCache<String>,
+    /// `guest-info` per VM, consulted before every other agent RPC.
+    agent_info_cache: TtlCache<AgentInfo>,
+    /// Why a VM's agent can't be reached (not connected, domain gone, ...),
+    /// kept for the negative TTL so no further agent RPCs are attempted.
+    agent_down: TtlCache<VirshError>,
+    dominfo_cache: TtlCache<DomInfo>,
+    domstats_cache: TtlCache<DomStats>,
+    net_cache: TtlCache<Vec<GuestInterface>>,
+    fs_cache: TtlCache<Vec<GuestFilesystem>>,
+    /// Set once `virsh domstats` has failed, so later scans go straight to
+    /// per-VM `dominfo` instead of paying for a doomed bulk call each time.
+    domstats_unavailable: AtomicBool,
+    /// Last known power state per VM, from `virsh list --all` or domstats.
+    states: Mutex<HashMap<String, DomainState>>,
+    uuids: Mutex<HashMap<String, String>>,
 }
 
 impl ProbeManager {
-    pub fn new(uri: String, timeout: Duration, cache_ttl: Duration) -> Result<Self> {
+    pub fn new(uri: String, timeout: Duration, ttls: CacheTtls) -> Result<Self> {
+        Self::with_virsh(Virsh::new(uri), timeout, ttls)
+    }
+
+    /// Build a manager around an existing client (e.g. one backed by fixtures).
+    pub fn with_virsh(virsh: Virsh, timeout: Duration, ttls: CacheTtls) -> Result<Self> {
         Ok(Self {
-            uri,
+            agent: AgentRouter::new(virsh.clone()),
+            virsh,
             timeout_secs: timeout.as_secs(),
-            cache_ttl,
-            cache: Mutex::new(HashMap::new()),
+            os_cache: TtlCache::new(ttls.os, ttls.negative),
+            agent_info_cache: TtlCache::new(ttls.os, ttls.negative),
+            agent_down: TtlCache::new(ttls.negative, ttls.negative),
+            dominfo_cache: TtlCache::new(ttls.dominfo, ttls.negative),
+            domstats_cache: TtlCache::new(ttls.dominfo, ttls.negative),
+            net_cache: TtlCache::new(ttls.net, ttls.negative),
+            fs_cache: TtlCache::new(ttls.fs, ttls.negative),
+            domstats_unavailable: AtomicBool::new(false),
+            states: Mutex::new(HashMap::new()),
+            uuids: Mutex::new(HashMap::new()),
         })
     }
 
-    /// Get OS string for a VM, using cache if fresh.
+    /// Talk to `vm`'s guest agent directly over the QGA unix socket at
+    /// `path` instead of via `virsh qemu-agent-command`.
+    pub fn with_agent_socket(mut self, vm: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.agent.add_socket(vm, path);
+        self
+    }
+
+    /// The virsh client bound to this manager's libvirt connection.
+    pub fn virsh(&self) -> &Virsh {
+        &self.virsh
+    }
+
+    /// Get OS string for a VM, using cache if fresh. A guest whose agent
+    /// answered none of the probes is remembered as unknown for the negative TTL.
     pub fn get_os(&self, vm: &str) -> Result<Option<String>> {
-        {
-            let c = self.cache.lock().unwrap();
-            if let Some((val, ts)) = c.get(vm) {
-                if ts.elapsed() < self.cache_ttl {
-                    return Ok(Some(val.clone()));
+        if let Some(cached) = self.os_cache.get(vm) {
+            return Ok(cached);
+        }
+        self.refresh_os(vm)
+    }
+
+    /// The last OS string ever detected for `vm`, without probing.
+    pub fn peek_os(&self, vm: &str) -> Option<String> {
+        self.os_cache.peek(vm)
+    }
+
+    /// Like `get_os`, but an expired OS string (e.g. restored from disk) is
+    /// returned as-is instead of blocking on a new probe.
+    pub fn get_os_or_stale(&self, vm: &str) -> Result<Option<String>> {
+        match self.os_cache.peek(vm) {
+            Some(os) => Ok(Some(os)),
+            None => self.get_os(vm),
+        }
+    }
+
+    /// Probe the OS now, ignoring the cache, and store the result. Tries
+    /// guest-get-osinfo, then guest-get-os, then guest-exec (/etc/os-release,
+    /// Windows `ver` / `systeminfo`), skipping RPCs the agent doesn't offer.
+    /// An unreachable agent ends the probe with its error.
+    pub fn refresh_os(&self, vm: &str) -> Result<Option<String>> {
+        type OsProbe = fn(&dyn AgentTransport, &str, u64) -> Result<Option<String>, VirshError>;
+        let probes: [(&[&str], OsProbe); 3] = [
+            (&["guest-get-osinfo"], agent::try_guest_get_osinfo),
+            (&["guest-get-os"], agent::try_guest_get_os),
+            (&["guest-exec", "guest-exec-status"], agent::try_guest_exec_os),
+        ];
+        for (commands, probe) in probes {
+            match self.agent_probe(vm, commands, || probe(&self.agent, vm, self.timeout_secs)) {
+                Ok(Some(Some(s))) => {
+                    self.os_cache.put(vm, Some(s.clone()));
+                    return Ok(Some(s));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.os_cache.put(vm, None);
+                    return Err(e.into());
                 }
             }
         }
 
-        // 1) guest-get-osinfo
-        if let Ok(Some(s)) = agent::try_guest_get_osinfo(vm, self.timeout_secs) {
-            self.store_cache(vm, &s);
-            return Ok(Some(s));
-        }
+        self.os_cache.put(vm, None);
+        Ok(None)
+    }
+
+    /// Version and enabled commands of `vm`'s guest agent from `guest-info`,
+    /// using cache if fresh. `None` (cached for the negative TTL) if the agent
+    /// didn't answer.
+    pub fn get_agent_info(&self, vm: &str) -> Option<AgentInfo> {
+        self.agent_gate(vm).ok().flatten()
+    }
 
-        // 2) guest-get-os
-        if let Ok(Some(s)) = agent::try_guest_get_os(vm, self.timeout_secs) {
-            self.store_cache(vm, &s);
-            return Ok(Some(s));
+    /// The last agent info ever read for `vm`, without probing.
+    pub fn peek_agent_info(&self, vm: &str) -> Option<AgentInfo> {
+        self.agent_info_cache.peek(vm)
+    }
+
+    /// `guest-info` for `vm`, cached. `Err` if the agent is known to be
+    /// unreachable; `Ok(None)` if it answered but `guest-info` failed, in
+    /// which case its capabilities are unknown and probes should just try.
+    fn agent_gate(&self, vm: &str) -> Result<Option<AgentInfo>, VirshError> {
+        if let Some(Some(e)) = self.agent_down.get(vm) {
+            return Err(e);
+        }
+        if let Some(cached) = self.agent_info_cache.get(vm) {
+            return Ok(cached);
+        }
+        match agent::try_guest_info(&self.agent, vm, self.timeout_secs) {
+            Ok(info) => {
+                self.agent_info_cache.put(vm, Some(info.clone()));
+                Ok(Some(info))
+            }
+            Err(e) => {
+                self.agent_info_cache.put(vm, None);
+                self.agent_failed(vm, e)
+            }
         }
+    }
 
-        // 3) guest-exec fallback (not implemented here; call into utils/virsh)
+    /// Remember `e` if it makes the agent unreachable and return it; any other
+    /// error only concerns the one RPC and becomes `Ok(None)`.
+    fn agent_failed<T>(&self, vm: &str, e: VirshError) -> Result<Option<T>, VirshError> {
+        self.note_agent_error(vm, &e);
+        if e.agent_unreachable() {
+            return Err(e);
+        }
         Ok(None)
     }
 
-    fn store_cache(&self, vm: &str, val: &str) {
-        let mut c = self.cache.lock().unwrap();
-        c.insert(vm.to_string(), (val.to_string(), Instant::now()));
+    /// Mark `vm`'s agent as down for the negative TTL if `e` makes it unreachable.
+    fn note_agent_error(&self, vm: &str, e: &VirshError) {
+        if e.agent_unreachable() {
+            self.agent_down.put(vm, Some(e.clone()));
+        }
+    }
+
+    /// Run one agent `probe` for `vm` if its agent offers all of `commands`.
+    /// `Ok(None)` if it doesn't or the RPC failed on its own; `Err` if the
+    /// agent can't be reached at all.
+    fn agent_probe<T>(
+        &self,
+        vm: &str,
+        commands: &[&str],
+        probe: impl FnOnce() -> Result<T, VirshError>,
+    ) -> Result<Option<T>, VirshError> {
+        match self.agent_call(vm, commands, probe) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) if e.agent_unreachable() => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Like `agent_probe`, but every failure is returned: a command the agent
+    /// doesn't offer is `AgentCommandUnsupported`, and an unreachable agent is
+    /// remembered for the negative TTL.
+    fn agent_call<T>(
+        &self,
+        vm: &str,
+        commands: &[&str],
+        probe: impl FnOnce() -> Result<T, VirshError>,
+    ) -> Result<T, VirshError> {
+        let info = self.agent_gate(vm)?;
+        if info.is_some_and(|info| !commands.iter().all(|c| info.supports(c))) {
+            return Err(VirshError::AgentCommandUnsupported(format!(
+                "guest agent of {} does not support {}",
+                vm,
+                commands.join("/")
+            )));
+        }
+        probe().inspect_err(|e| self.note_agent_error(vm, e))
+    }
+
+    /// Network interfaces of `vm`, using cache if fresh: the guest agent's
+    /// `guest-network-get-interfaces` first, then `virsh domifaddr` from the
+    /// libvirt DHCP leases and finally the host ARP table, which work without
+    /// an agent. `None` (cached for the negative TTL) if every source failed
+    /// or came back empty.
+    pub fn get_interfaces(&self, vm: &str) -> Option<Vec<GuestInterface>> {
+        if let Some(cached) = self.net_cache.get(vm) {
+            return cached;
+        }
+        let found = self
+            .agent_probe(vm, &["guest-network-get-interfaces"], || {
+                agent::try_guest_network_interfaces(&self.agent, vm, self.timeout_secs)
+            })
+            .ok()
+            .flatten()
+            .filter(|ifaces| !ifaces.is_empty())
+            .or_else(|| {
+                ["lease", "arp"].iter().find_map(|source| {
+                    let raw = self.virsh.domifaddr_raw(vm, source).ok()?;
+                    Some(utils::parse_domifaddr(&raw)).filter(|ifaces| !ifaces.is_empty())
+                })
+            });
+        self.net_cache.put(vm, found.clone());
+        found
+    }
+
+    /// The last interfaces ever found for `vm`, without probing.
+    pub fn peek_interfaces(&self, vm: &str) -> Option<Vec<GuestInterface>> {
+        self.net_cache.peek(vm)
+    }
+
+    /// Mounted filesystems of `vm` from the guest agent's `guest-get-fsinfo`,
+    /// using cache if fresh. The first failure is returned as an error; while
+    /// it is cached the result is `Ok(None)`.
+    pub fn get_filesystems(&self, vm: &str) -> Result<Option<Vec<GuestFilesystem>>> {
+        if let Some(cached) = self.fs_cache.get(vm) {
+            return Ok(cached);
+        }
+        let result = self.agent_call(vm, &["guest-get-fsinfo"], || {
+            agent::try_guest_fsinfo(&self.agent, vm, self.timeout_secs)
+        });
+        self.fs_cache.put(vm, result.as_ref().ok().cloned());
+        Ok(Some(result?))
+    }
+
+    /// Get parsed `virsh dominfo` for a VM, using cache if fresh. The first
+    /// failure is returned as an error; while it is cached the result is `Ok(None)`.
+    pub fn get_dominfo(&self, vm: &str) -> Result<Option<DomInfo>> {
+        if let Some(cached) = self.dominfo_cache.get(vm) {
+            return Ok(cached);
+        }
+        match self.virsh.dominfo_raw(vm) {
+            Ok(raw) => {
+                let info = utils::parse_dominfo(&raw);
+                self.dominfo_cache.put(vm, Some(info.clone()));
+                Ok(Some(info))
+            }
+            Err(e) => {
+                self.dominfo_cache.put(vm, None);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Read `virsh dominfo` now, bypassing the cache (the fresh result still
+    /// replaces the cached one). `Ok(None)` if the domain reports no CPU time,
+    /// e.g. because it is shut off.
+    pub fn sample_cpu(&self, vm: &str) -> Result<Option<CpuSample>> {
+        match self.virsh.dominfo_raw(vm) {
+            Ok(raw) => {
+                let at = Instant::now();
+                let info = utils::parse_dominfo(&raw);
+                self.dominfo_cache.put(vm, Some(info.clone()));
+                let cpu_secs = info.cpu_time.as_deref().and_then(utils::parse_cpu_time_to_secs_f64);
+                Ok(cpu_secs.map(|cpu_secs| CpuSample { at, cpu_secs, vcpus: info.vcpus }))
+            }
+            Err(e) => {
+                self.dominfo_cache.put(vm, None);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Query every domain with a single `virsh domstats` call, refreshing the
+    /// dominfo and domstats caches for all of them, and return a CPU sample
+    /// for each domain that reports CPU time. Fails if domstats is unavailable
+    /// (older libvirt, restricted connection), after which callers should fall
+    /// back to `sample_cpu` per VM. Only a failure meaning domstats isn't
+    /// supported is remembered for this run; timeouts and dropped connections
+    /// are retried on the next call.
+    pub fn sample_domstats(&self) -> Result<HashMap<String, CpuSample>> {
+        if self.domstats_unavailable.load(Ordering::Relaxed) {
+            bail!("virsh domstats is unavailable on this connection");
+        }
+        let raw = match self.virsh.domstats_raw() {
+            Ok(raw) => raw,
+            Err(e) => {
+                // An old virsh fails with "unknown command" or an unknown
+                // option, which is not classified further.
+                if matches!(e, VirshError::Failed { .. } | VirshError::PermissionDenied(_)) {
+                    self.domstats_unavailable.store(true, Ordering::Relaxed);
+                }
+                return Err(e.into());
+            }
+        };
+        let at = Instant::now();
+        let mut samples = HashMap::new();
+        for dom in stats::parse_domstats(&raw) {
+            self.dominfo_cache.put(&dom.name, Some(dom.to_dominfo()));
+            if let Some(state) = dom.state.and_then(DomainState::from_code) {
+                self.states.lock().unwrap().insert(dom.name.clone(), state);
+            }
+            if let Some(cpu_secs) = dom.cpu_time_secs() {
+                samples.insert(dom.name.clone(), CpuSample { at, cpu_secs, vcpus: dom.vcpu_current });
+            }
+            let name = dom.name.clone();
+            self.domstats_cache.put(&name, Some(dom));
+        }
+        Ok(samples)
+    }
+
+    /// The full domstats record of `vm` from the last bulk query, if fresh.
+    pub fn get_domstats(&self, vm: &str) -> Option<DomStats> {
+        self.domstats_cache.get(vm).flatten()
+    }
+
+    /// All domains with their state from `virsh list --all`, remembering the
+    /// states for `domain_state`.
+    pub fn list_domains(&self) -> Result<Vec<(String, Option<DomainState>)>> {
+        let doms = self.virsh.list_domains()?;
+        let mut states = self.states.lock().unwrap();
+        for (vm, state) in &doms {
+            match state {
+                Some(state) => states.insert(vm.clone(), *state),
+                None => states.remove(vm),
+            };
+        }
+        Ok(doms)
+    }
+
+    /// Last known power state of `vm`, if it has been listed or sampled.
+    pub fn domain_state(&self, vm: &str) -> Option<DomainState> {
+        self.states.lock().unwrap().get(vm).copied()
+    }
+
+    /// Domain UUID of `vm` from `virsh domuuid`, remembered for this run.
+    pub fn domain_uuid(&self, vm: &str) -> Result<String> {
+        if let Some(uuid) = self.uuids.lock().unwrap().get(vm) {
+            return Ok(uuid.clone());
+        }
+        let uuid = self.virsh.domuuid(vm)?;
+        self.uuids.lock().unwrap().insert(vm.to_string(), uuid.clone());
+        Ok(uuid)
+    }
+
+    /// Seed the OS cache from a persisted snapshot. Entries are only restored
+    /// for VMs whose current UUID (already looked up via `domain_uuid`) matches
+    /// the stored one. Returns the restored VMs whose entry is past the OS TTL
+    /// and should be refreshed.
+    pub fn restore_os(&self, persisted: &PersistedCache) -> Vec<String> {
+        let uuids = self.uuids.lock().unwrap().clone();
+        let mut stale = Vec::new();
+        for (vm, entry) in &persisted.entries {
+            if uuids.get(vm) != Some(&entry.uuid) {
+                continue;
+            }
+            self.os_cache.put_at(vm, entry.os.clone(), entry.probed_at_time());
+            if self.os_cache.get(vm).is_none() {
+                stale.push(vm.clone());
+            }
+        }
+        stale
+    }
+
+    /// Snapshot every known OS string with its domain UUID for persisting.
+    pub fn snapshot_os(&self) -> PersistedCache {
+        let uuids = self.uuids.lock().unwrap().clone();
+        let mut snapshot = PersistedCache::default();
+        for (vm, os, probed_at) in self.os_cache.positives() {
+            if let Some(uuid) = uuids.get(&vm) {
+                let entry = PersistedEntry { uuid: uuid.clone(), os, probed_at: persist::unix_secs(probed_at) };
+                snapshot.entries.insert(vm, entry);
+            }
+        }
+        snapshot
     }
 }
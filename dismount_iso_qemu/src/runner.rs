@@ -0,0 +1,97 @@
+// src/runner.rs
+use std::collections::HashMap;
+use std::io;
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Captured result of one external command.
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Executes `virsh` with the given arguments. `Virsh` goes through this trait
+/// for every call, so the virsh, agent and probe layers can run against
+/// recorded outputs (`FixtureRunner`) instead of a live libvirt.
+pub trait CommandRunner: Send + Sync {
+    fn run(&self, args: &[&str]) -> io::Result<CommandOutput>;
+}
+
+/// Spawns the real `virsh` binary under the C locale, so labels, number
+/// formatting and error messages are the English ones the parsers expect.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, args: &[&str]) -> io::Result<CommandOutput> {
+        let out = Command::new("virsh").args(args).env("LC_ALL", "C").output()?;
+        Ok(CommandOutput {
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&out.stderr).to_string(),
+        })
+    }
+}
+
+/// Replays recorded virsh outputs keyed by the space-joined arguments, with
+/// the leading `-c <uri>` stripped so fixtures don't depend on the connection.
+///
+/// Registering several outputs for the same command replays them in order and
+/// then keeps returning the last one (useful for polling RPCs). Commands with
+/// no fixture fail like virsh would, with a message naming the missing key.
+#[derive(Debug, Default)]
+pub struct FixtureRunner {
+    responses: Mutex<HashMap<String, Vec<CommandOutput>>>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl FixtureRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a successful run of `virsh <args>` printing `stdout`.
+    pub fn ok(self, args: &str, stdout: impl Into<String>) -> Self {
+        self.push(args, CommandOutput { success: true, stdout: stdout.into(), stderr: String::new() })
+    }
+
+    /// Record a failing run of `virsh <args>` printing `stderr`.
+    pub fn fail(self, args: &str, stderr: impl Into<String>) -> Self {
+        self.push(args, CommandOutput { success: false, stdout: String::new(), stderr: stderr.into() })
+    }
+
+    fn push(self, args: &str, out: CommandOutput) -> Self {
+        self.responses.lock().unwrap().entry(args.to_string()).or_default().push(out);
+        self
+    }
+
+    /// Every command run so far, full argument list (including `-c <uri>`).
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for FixtureRunner {
+    fn run(&self, args: &[&str]) -> io::Result<CommandOutput> {
+        self.calls.lock().unwrap().push(args.join(" "));
+
+        let key_args = match args {
+            ["-c", _, rest @ ..] => rest,
+            _ => args,
+        };
+        let key = key_args.join(" ");
+
+        let mut responses = self.responses.lock().unwrap();
+        match responses.get_mut(&key) {
+            Some(queue) if queue.len() > 1 => Ok(queue.remove(0)),
+            Some(queue) if !queue.is_empty() => Ok(queue[0].clone()),
+            _ => Ok(CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: format!("error: no fixture recorded for `virsh {}`", key),
+            }),
+        }
+    }
+}
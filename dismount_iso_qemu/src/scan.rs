@@ -0,0 +1,213 @@
+// src/scan.rs
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::probe::{CpuSample, ProbeManager};
+use serde::Serialize;
+use clap::ValueEnum;
+use crate::utils::{self, Bytes, DomInfo, DomainState, GuestInterface};
+
+/// Probe results for one VM: the record behind the status table and the
+/// JSON/CSV output. Memory serializes as a byte count.
+#[derive(Debug, Clone, Serialize)]
+pub struct VmStatus {
+    pub name: String,
+    /// Power state, `None` if it couldn't be determined.
+    pub state: Option<DomainState>,
+    /// Detected OS, `None` when the guest agent gave no answer.
+    pub os: Option<String>,
+    /// Why the OS probe failed, if it errored rather than came back empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_error: Option<String>,
+    /// Guest agent version from `guest-info`, `None` without a reachable agent.
+    pub agent_version: Option<String>,
+    pub memory_used_bytes: Option<Bytes>,
+    pub memory_max_bytes: Option<Bytes>,
+    pub cpu_time_seconds: Option<u64>,
+    pub vcpus: Option<u32>,
+    /// CPU utilisation over the scan, normalised by vCPU count (0-100).
+    pub cpu_percent: Option<f64>,
+    /// Guest network interfaces with their MACs and IP addresses.
+    pub interfaces: Vec<GuestInterface>,
+    /// Unparsed `CPU time:` value, kept for the table when parsing fails.
+    #[serde(skip)]
+    pub cpu_time_raw: Option<String>,
+}
+
+impl VmStatus {
+    pub fn new(name: &str, os: anyhow::Result<Option<String>>, dominfo: &DomInfo) -> Self {
+        let (os, os_error) = match os {
+            Ok(os) => (os, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Self {
+            name: name.to_string(),
+            state: None,
+            os,
+            os_error,
+            agent_version: None,
+            memory_used_bytes: dominfo.used_memory,
+            memory_max_bytes: dominfo.max_memory,
+            cpu_time_seconds: dominfo.cpu_time.as_deref().and_then(utils::parse_cpu_time_to_seconds),
+            vcpus: dominfo.vcpus,
+            cpu_percent: None,
+            interfaces: Vec::new(),
+            cpu_time_raw: dominfo.cpu_time.clone(),
+        }
+    }
+}
+
+/// Which domains to include, by power state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StateFilter {
+    /// Running (or idle) guests
+    Running,
+    /// Shut-off or crashed domains
+    Inactive,
+}
+
+impl StateFilter {
+    pub fn matches(self, state: Option<DomainState>) -> bool {
+        match self {
+            StateFilter::Running => state.is_some_and(DomainState::is_running),
+            StateFilter::Inactive => state.is_some_and(DomainState::is_inactive),
+        }
+    }
+}
+
+/// Names of all domains (via `virsh list --all`, which also records their
+/// states in `mgr`), optionally restricted to one `filter`.
+pub fn list_vms(mgr: &ProbeManager, filter: Option<StateFilter>) -> anyhow::Result<Vec<String>> {
+    Ok(mgr
+        .list_domains()?
+        .into_iter()
+        .filter(|(_, state)| filter.is_none_or(|f| f.matches(*state)))
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// Minimum wall time between the two CPU samples used for `cpu_percent`.
+pub const CPU_SAMPLE_GAP: Duration = Duration::from_secs(1);
+
+/// Probe OS and dominfo for every VM on a pool of at most `workers` threads.
+/// With `allow_stale`, an expired OS string already in the cache (restored
+/// from disk) is shown instead of re-probing the guest. Rows come back in the
+/// order of `vms` without `cpu_percent`, so the scan never waits for a second
+/// CPU sample; see `scan_vms_with_cpu`.
+pub fn scan_vms(mgr: &ProbeManager, vms: &[String], workers: usize, allow_stale: bool) -> Vec<VmStatus> {
+    // One bulk sample still fills the dominfo cache in a single domstats call.
+    sample_cpu_all(mgr, vms, workers);
+    parallel_map(vms, workers, |vm| probe_vm(mgr, vm, allow_stale))
+}
+
+/// Like `scan_vms` without `allow_stale`, plus CPU utilisation, which needs
+/// two uncached samples: the first is taken before the OS probes and the
+/// second after them, so slow agents cover the gap and the scan only waits
+/// for whatever is left of `CPU_SAMPLE_GAP`. Each sample is one bulk
+/// `virsh domstats` call, which also fills the dominfo cache.
+pub fn scan_vms_with_cpu(mgr: &ProbeManager, vms: &[String], workers: usize) -> Vec<VmStatus> {
+    let first = sample_cpu_all(mgr, vms, workers);
+    // Timed from after the first sample, whose timestamps are taken as each
+    // VM's answer arrives, so the samples are never less than the gap apart.
+    let started = Instant::now();
+    let mut rows = parallel_map(vms, workers, |vm| probe_vm(mgr, vm, false));
+
+    if first.iter().any(Option::is_some) {
+        thread::sleep(CPU_SAMPLE_GAP.saturating_sub(started.elapsed()));
+        let sampled: Vec<usize> = (0..vms.len()).filter(|&i| first[i].is_some()).collect();
+        let names: Vec<String> = sampled.iter().map(|&i| vms[i].clone()).collect();
+        let second = sample_cpu_all(mgr, &names, workers);
+        for (i, after) in sampled.into_iter().zip(second) {
+            if let (Some(before), Some(after)) = (&first[i], after) {
+                rows[i].cpu_percent = after.percent_since(before);
+            }
+        }
+    }
+    rows
+}
+
+/// Probe what an `allow_stale` scan only read from the caches, for a
+/// background thread after the startup table: agent info and interfaces of
+/// every running VM, and the OS of those in `stale`.
+pub fn refresh_stale(mgr: &ProbeManager, vms: &[String], stale: &[String], workers: usize) {
+    parallel_map(vms, workers, |vm| {
+        if !mgr.domain_state(vm).is_none_or(DomainState::is_running) {
+            return;
+        }
+        if stale.contains(vm) {
+            let _ = mgr.refresh_os(vm);
+        }
+        mgr.get_agent_info(vm);
+        mgr.get_interfaces(vm);
+    });
+}
+
+/// One CPU sample per VM (in order): a single `virsh domstats` call when the
+/// connection supports it, otherwise `virsh dominfo` for each VM.
+fn sample_cpu_all(mgr: &ProbeManager, vms: &[String], workers: usize) -> Vec<Option<CpuSample>> {
+    match mgr.sample_domstats() {
+        Ok(mut samples) => vms.iter().map(|vm| samples.remove(vm)).collect(),
+        Err(_) => parallel_map(vms, workers, |vm| mgr.sample_cpu(vm).ok().flatten()),
+    }
+}
+
+/// Apply `f` to every item on a pool of at most `workers` threads. Each worker
+/// pulls the next unprocessed item, so one guest stuck on the agent timeout
+/// only holds up its own slot. Results are returned in input order.
+pub fn parallel_map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let slots: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    let workers = workers.clamp(1, items.len().max(1));
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(idx) else { break };
+                    let result = f(item);
+                    slots.lock().unwrap()[idx] = Some(result);
+                }
+            });
+        }
+    });
+
+    slots.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+fn probe_vm(mgr: &ProbeManager, vm: &str, allow_stale: bool) -> VmStatus {
+    // OS probe (cached by ProbeManager). A guest that isn't running has no
+    // agent to ask, so it shows the last OS seen, if any.
+    let state = mgr.domain_state(vm);
+    let os = match state {
+        Some(st) if !st.is_running() => Ok(mgr.peek_os(vm)),
+        _ if allow_stale => mgr.get_os_or_stale(vm),
+        _ => mgr.get_os(vm),
+    };
+
+    // dominfo probe (cached by ProbeManager)
+    let dominfo = mgr.get_dominfo(vm).ok().flatten().unwrap_or_default();
+
+    let mut row = VmStatus::new(vm, os, &dominfo);
+    row.state = mgr.domain_state(vm);
+    let interfaces = match state {
+        Some(st) if !st.is_running() => None,
+        _ if allow_stale => mgr.peek_interfaces(vm),
+        _ => mgr.get_interfaces(vm),
+    };
+    row.interfaces = interfaces.unwrap_or_default();
+    // Already read by the OS probe unless the OS came from the persisted cache.
+    let agent = match state {
+        Some(st) if !st.is_running() => mgr.peek_agent_info(vm),
+        _ if allow_stale => mgr.peek_agent_info(vm),
+        _ => mgr.get_agent_info(vm),
+    };
+    row.agent_version = agent.map(|a| a.version).filter(|v| !v.is_empty());
+    row
+}
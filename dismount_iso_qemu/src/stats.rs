@@ -0,0 +1,125 @@
+// src/stats.rs
+use crate::utils::{Bytes, DomInfo};
+
+/// Per-disk counters from the `block.<n>.*` fields of `virsh domstats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    pub name: String,
+    pub rd_bytes: u64,
+    pub wr_bytes: u64,
+    pub rd_reqs: u64,
+    pub wr_reqs: u64,
+}
+
+/// Per-interface counters from the `net.<n>.*` fields of `virsh domstats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetStats {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_pkts: u64,
+    pub tx_pkts: u64,
+}
+
+/// One domain's record from `virsh domstats`. Inactive domains only report a
+/// subset (state and balloon sizes), so most fields are optional.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DomStats {
+    pub name: String,
+    /// `virDomainState` number (1 = running, 3 = paused, 5 = shut off, ...).
+    pub state: Option<u32>,
+    pub state_reason: Option<u32>,
+    pub cpu_time_ns: Option<u64>,
+    pub cpu_user_ns: Option<u64>,
+    pub cpu_system_ns: Option<u64>,
+    pub balloon_current_kib: Option<u64>,
+    pub balloon_maximum_kib: Option<u64>,
+    pub balloon_rss_kib: Option<u64>,
+    pub vcpu_current: Option<u32>,
+    pub vcpu_maximum: Option<u32>,
+    pub block: Vec<BlockStats>,
+    pub net: Vec<NetStats>,
+}
+
+impl DomStats {
+    /// CPU time in seconds, with the nanosecond precision domstats reports.
+    pub fn cpu_time_secs(&self) -> Option<f64> {
+        self.cpu_time_ns.map(|ns| ns as f64 / 1e9)
+    }
+
+    /// The subset `virsh dominfo` would have reported, so callers written
+    /// against `DomInfo` work unchanged on top of a bulk query.
+    pub fn to_dominfo(&self) -> DomInfo {
+        DomInfo {
+            max_memory: self.balloon_maximum_kib.map(Bytes::from_kib),
+            used_memory: self.balloon_current_kib.map(Bytes::from_kib),
+            cpu_time: self.cpu_time_secs().map(|s| format!("{:.1}s", s)),
+            vcpus: self.vcpu_current,
+        }
+    }
+}
+
+/// Parse `virsh domstats` output: a `Domain: '<name>'` header per domain
+/// followed by indented `key=value` lines. Unknown keys are ignored.
+pub fn parse_domstats(s: &str) -> Vec<DomStats> {
+    let mut doms: Vec<DomStats> = Vec::new();
+
+    for line in s.lines() {
+        let l = line.trim();
+        if let Some(name) = l.strip_prefix("Domain:") {
+            let name = name.trim();
+            let name = name.strip_prefix('\'').and_then(|n| n.strip_suffix('\'')).unwrap_or(name);
+            doms.push(DomStats { name: name.to_string(), ..Default::default() });
+            continue;
+        }
+        let (Some(dom), Some((key, val))) = (doms.last_mut(), l.split_once('=')) else { continue };
+        let num = val.trim().parse::<u64>().ok();
+
+        match key.split('.').collect::<Vec<_>>().as_slice() {
+            ["state", "state"] => dom.state = num.map(|n| n as u32),
+            ["state", "reason"] => dom.state_reason = num.map(|n| n as u32),
+            ["cpu", "time"] => dom.cpu_time_ns = num,
+            ["cpu", "user"] => dom.cpu_user_ns = num,
+            ["cpu", "system"] => dom.cpu_system_ns = num,
+            ["balloon", "current"] => dom.balloon_current_kib = num,
+            ["balloon", "maximum"] => dom.balloon_maximum_kib = num,
+            ["balloon", "rss"] => dom.balloon_rss_kib = num,
+            ["vcpu", "current"] => dom.vcpu_current = num.map(|n| n as u32),
+            ["vcpu", "maximum"] => dom.vcpu_maximum = num.map(|n| n as u32),
+            ["block", idx, rest @ ..] => {
+                let Ok(idx) = idx.parse::<usize>() else { continue };
+                let b = slot(&mut dom.block, idx);
+                match rest {
+                    ["name"] => b.name = val.trim().to_string(),
+                    ["rd", "bytes"] => b.rd_bytes = num.unwrap_or(0),
+                    ["wr", "bytes"] => b.wr_bytes = num.unwrap_or(0),
+                    ["rd", "reqs"] => b.rd_reqs = num.unwrap_or(0),
+                    ["wr", "reqs"] => b.wr_reqs = num.unwrap_or(0),
+                    _ => {}
+                }
+            }
+            ["net", idx, rest @ ..] => {
+                let Ok(idx) = idx.parse::<usize>() else { continue };
+                let n = slot(&mut dom.net, idx);
+                match rest {
+                    ["name"] => n.name = val.trim().to_string(),
+                    ["rx", "bytes"] => n.rx_bytes = num.unwrap_or(0),
+                    ["tx", "bytes"] => n.tx_bytes = num.unwrap_or(0),
+                    ["rx", "pkts"] => n.rx_pkts = num.unwrap_or(0),
+                    ["tx", "pkts"] => n.tx_pkts = num.unwrap_or(0),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    doms
+}
+
+/// The `idx`-th element of `v`, growing it with defaults as needed.
+fn slot<T: Default>(v: &mut Vec<T>, idx: usize) -> &mut T {
+    if v.len() <= idx {
+        v.resize_with(idx + 1, T::default);
+    }
+    &mut v[idx]
+}
@@ -1,48 +1,510 @@
 // src/utils.rs
+use std::fmt;
+use serde::Serialize;
+
+/// A memory size in bytes. Parsed from the `<n> <unit>` values virsh prints
+/// (`8388608 KiB`), displayed with binary units and serialized as a plain
+/// byte count.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct Bytes(pub u64);
+
+impl Bytes {
+    pub fn from_kib(kib: u64) -> Self {
+        Bytes(kib.saturating_mul(1024))
+    }
+
+    /// Parse a virsh quantity such as `8388608 KiB`, `512 MiB` or `4096 B`.
+    /// Units are binary and case-insensitive; a missing or unknown unit is
+    /// rejected rather than guessed.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let n: u64 = s[..split].parse().ok()?;
+        let shift = match s[split..].trim().to_ascii_lowercase().as_str() {
+            "b" | "bytes" => 0,
+            "kib" => 10,
+            "mib" => 20,
+            "gib" => 30,
+            "tib" => 40,
+            _ => return None,
+        };
+        n.checked_mul(1u64 << shift).map(Bytes)
+    }
+}
+
+impl fmt::Display for Bytes {
+    /// Binary units: whole numbers up to KiB, one decimal from MiB up.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut unit = 0usize;
+        let mut scaled = self.0;
+        while scaled >= 1024 && unit < UNITS.len() - 1 {
+            scaled /= 1024;
+            unit += 1;
+        }
+        if unit >= 2 {
+            let value = self.0 as f64 / 1024u64.pow(unit as u32) as f64;
+            write!(f, "{:.1} {}", value, UNITS[unit])
+        } else {
+            write!(f, "{} {}", scaled, UNITS[unit])
+        }
+    }
+}
 
 /// Small struct to hold parsed dominfo values.
-/// Note: field names use `_mb` to match existing callers, but many libvirt
-/// installations report memory in KiB. The caller is responsible for treating
-/// these numeric values appropriately (we provide `format_memory_kib`).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DomInfo {
-    pub max_memory_mb: Option<u64>,
-    pub used_memory_mb: Option<u64>,
+    pub max_memory: Option<Bytes>,
+    pub used_memory: Option<Bytes>,
     pub cpu_time: Option<String>, // keep human string like "613h 33m 33s" or "154359.4s"
+    pub vcpus: Option<u32>,
 }
 
-/// Parse `virsh dominfo` output for Max memory, Used memory, and CPU time.
-/// This function extracts the first numeric token after the colon for memory
-/// lines and the remainder of the line for CPU time.
+/// Parse `virsh dominfo` output for Max memory, Used memory, CPU time and CPU(s).
+/// Memory lines are parsed with their unit into `Bytes`; CPU time keeps the
+/// remainder of the line. Labels are matched in
+/// English first; anything not found that way (translated labels) is filled in
+/// from the shape of the values, see `dominfo_by_shape`.
 pub fn parse_dominfo(s: &str) -> DomInfo {
-    let mut max_memory_mb = None;
-    let mut used_memory_mb = None;
+    let mut max_memory = None;
+    let mut used_memory = None;
     let mut cpu_time = None;
+    let mut vcpus = None;
 
     for line in s.lines() {
         let l = line.trim();
-        if l.starts_with("Max memory:") {
-            if let Some(val) = l.splitn(2, ':').nth(1) {
-                let v = val.trim().split_whitespace().next().unwrap_or("");
-                if let Ok(n) = v.parse::<u64>() {
-                    max_memory_mb = Some(n);
-                }
-            }
-        } else if l.starts_with("Used memory:") {
-            if let Some(val) = l.splitn(2, ':').nth(1) {
-                let v = val.trim().split_whitespace().next().unwrap_or("");
-                if let Ok(n) = v.parse::<u64>() {
-                    used_memory_mb = Some(n);
-                }
-            }
-        } else if l.starts_with("CPU time:") {
-            if let Some(val) = l.splitn(2, ':').nth(1) {
-                cpu_time = Some(val.trim().to_string());
-            }
+        if let Some(val) = l.strip_prefix("Max memory:") {
+            max_memory = Bytes::parse(val);
+        } else if let Some(val) = l.strip_prefix("Used memory:") {
+            used_memory = Bytes::parse(val);
+        } else if l.starts_with("CPU time:")
+            && let Some((_, val)) = l.split_once(':')
+        {
+            cpu_time = Some(val.trim().replace(',', "."));
+        } else if let Some(val) = l.strip_prefix("CPU(s):") {
+            vcpus = val.trim().parse::<u32>().ok();
+        }
+    }
+
+    if max_memory.is_none() || used_memory.is_none() || cpu_time.is_none() || vcpus.is_none() {
+        let shaped = dominfo_by_shape(s);
+        max_memory = max_memory.or(shaped.max_memory);
+        used_memory = used_memory.or(shaped.used_memory);
+        cpu_time = cpu_time.or(shaped.cpu_time);
+        vcpus = vcpus.or(shaped.vcpus);
+    }
+
+    DomInfo { max_memory, used_memory, cpu_time, vcpus }
+}
+
+/// Locale-neutral reading of `virsh dominfo`: only the labels are translated,
+/// the values and their order are fixed by virsh. The first two `<n> KiB`
+/// values are max and used memory, a `<float>s` value is the CPU time (with a
+/// `,` decimal separator in some locales), and CPU(s) is the last plain
+/// integer before the memory lines (after `Id`, which is `-` when shut off).
+fn dominfo_by_shape(s: &str) -> DomInfo {
+    let mut mem = Vec::new();
+    let mut info = DomInfo::default();
+
+    for line in s.lines() {
+        let Some((_, val)) = line.split_once(':') else { continue };
+        let val = val.trim();
+        if let Some(b) = Bytes::parse(val) {
+            mem.push(b);
+        } else if !mem.is_empty() {
+            continue;
+        } else if let Ok(n) = val.parse::<u32>() {
+            info.vcpus = Some(n);
+        } else if let Some(secs) = val.strip_suffix('s')
+            && !secs.is_empty()
+            && secs.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+        {
+            info.cpu_time = Some(val.replace(',', "."));
+        }
+    }
+    info.max_memory = mem.first().copied();
+    info.used_memory = mem.get(1).copied();
+    info
+}
+
+/// One row of `virsh domblklist --details` output.
+#[derive(Debug, Clone)]
+pub struct BlockDevice {
+    pub device: String,   // "disk", "cdrom", "floppy"
+    pub target: String,   // "vda", "sda", "hdc", ...
+    pub source: Option<String>, // None when virsh prints "-" (empty tray)
+}
+
+/// Split a table row into at most `n` whitespace-separated columns; the last
+/// column keeps any embedded spaces (file paths, state names like "shut off").
+pub fn split_columns(line: &str, n: usize) -> Vec<&str> {
+    let mut cols = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if cols.len() + 1 == n {
+            cols.push(rest);
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        cols.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    cols
+}
+
+/// Domain power state as shown by `virsh list` / `virsh domstate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainState {
+    NoState,
+    Running,
+    /// Running but blocked on a resource (`idle` in virsh output).
+    Idle,
+    Paused,
+    InShutdown,
+    ShutOff,
+    Crashed,
+    PmSuspended,
+}
+
+impl DomainState {
+    /// Parse the state column of `virsh list`.
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s.trim() {
+            "no state" => DomainState::NoState,
+            "running" => DomainState::Running,
+            "idle" | "blocked" => DomainState::Idle,
+            "paused" => DomainState::Paused,
+            "in shutdown" => DomainState::InShutdown,
+            "shut off" => DomainState::ShutOff,
+            "crashed" => DomainState::Crashed,
+            "pmsuspended" => DomainState::PmSuspended,
+            _ => return None,
+        })
+    }
+
+    /// Map a `virDomainState` number (`state.state` in `virsh domstats`).
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => DomainState::NoState,
+            1 => DomainState::Running,
+            2 => DomainState::Idle,
+            3 => DomainState::Paused,
+            4 => DomainState::InShutdown,
+            5 => DomainState::ShutOff,
+            6 => DomainState::Crashed,
+            7 => DomainState::PmSuspended,
+            _ => return None,
+        })
+    }
+
+    /// Identifier used in machine-readable output (JSON and CSV), e.g.
+    /// `shut_off`; `Display` keeps virsh's spelling for the table.
+    pub fn key(self) -> &'static str {
+        match self {
+            DomainState::NoState => "no_state",
+            DomainState::Running => "running",
+            DomainState::Idle => "idle",
+            DomainState::Paused => "paused",
+            DomainState::InShutdown => "in_shutdown",
+            DomainState::ShutOff => "shut_off",
+            DomainState::Crashed => "crashed",
+            DomainState::PmSuspended => "pm_suspended",
+        }
+    }
+
+    /// True when the guest is executing, i.e. its agent can answer.
+    pub fn is_running(self) -> bool {
+        matches!(self, DomainState::Running | DomainState::Idle)
+    }
+
+    /// True when the domain has no running QEMU process.
+    pub fn is_inactive(self) -> bool {
+        matches!(self, DomainState::ShutOff | DomainState::Crashed)
+    }
+}
+
+impl Serialize for DomainState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.key())
+    }
+}
+
+impl fmt::Display for DomainState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DomainState::NoState => "no state",
+            DomainState::Running => "running",
+            DomainState::Idle => "idle",
+            DomainState::Paused => "paused",
+            DomainState::InShutdown => "in shutdown",
+            DomainState::ShutOff => "shut off",
+            DomainState::Crashed => "crashed",
+            DomainState::PmSuspended => "pmsuspended",
+        })
+    }
+}
+
+/// Parse `virsh list --all` (Id, Name, State) into names and states. Rows
+/// with a state this tool doesn't know are kept with `None`.
+pub fn parse_list_all(s: &str) -> Vec<(String, Option<DomainState>)> {
+    let mut doms = Vec::new();
+    let mut in_body = false;
+
+    for line in s.lines() {
+        let l = line.trim();
+        if !in_body {
+            in_body = l.starts_with("---");
+            continue;
+        }
+        let cols = split_columns(l, 3);
+        if cols.len() < 2 {
+            continue;
+        }
+        doms.push((cols[1].to_string(), cols.get(2).and_then(|st| DomainState::parse(st))));
+    }
+
+    doms
+}
+
+/// Parse `virsh domblklist --details` output (the Type column is skipped). Rows before the dashed separator
+/// are treated as the header; sources may contain spaces so everything after
+/// the third column is kept as the source path.
+pub fn parse_domblklist(s: &str) -> Vec<BlockDevice> {
+    let mut devices = Vec::new();
+    let mut in_body = false;
+
+    for line in s.lines() {
+        let l = line.trim();
+        if !in_body {
+            in_body = l.starts_with("---");
+            continue;
+        }
+        let cols = split_columns(l, 4);
+        if cols.len() < 3 {
+            continue;
+        }
+        let source = cols.get(3).filter(|src| !src.is_empty() && **src != "-");
+        devices.push(BlockDevice {
+            device: cols[1].to_string(),
+            target: cols[2].to_string(),
+            source: source.map(|src| src.to_string()),
+        });
+    }
+
+    devices
+}
+
+/// One guest network interface with its addresses (prefix length dropped).
+/// Loopback interfaces and link-local addresses are left out by the parsers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GuestInterface {
+    pub name: String,
+    pub mac: Option<String>,
+    pub addresses: Vec<String>,
+}
+
+/// Addresses not worth showing: loopback and IPv6 link-local.
+fn is_uninteresting_addr(addr: &str) -> bool {
+    addr.starts_with("127.") || addr == "::1" || addr.to_ascii_lowercase().starts_with("fe80:")
+}
+
+/// Parse the `return` array of the `guest-network-get-interfaces` agent RPC.
+pub fn parse_guest_interfaces(ret: &serde_json::Value) -> Vec<GuestInterface> {
+    let mut ifaces = Vec::new();
+    for iface in ret.as_array().into_iter().flatten() {
+        let name = iface.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string();
+        if name == "lo" {
+            continue;
+        }
+        let addresses: Vec<String> = iface
+            .get("ip-addresses")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|a| a.get("ip-address").and_then(|v| v.as_str()))
+            .filter(|a| !is_uninteresting_addr(a))
+            .map(|a| a.to_string())
+            .collect();
+        if addresses.is_empty() {
+            continue;
+        }
+        let mac = iface.get("hardware-address").and_then(|v| v.as_str()).map(|m| m.to_string());
+        ifaces.push(GuestInterface { name, mac, addresses });
+    }
+    ifaces
+}
+
+/// Parse `virsh domifaddr` output (Name, MAC address, Protocol, Address).
+/// Extra addresses of the same interface are printed on rows whose name and
+/// MAC columns are `-`; they are folded into the preceding interface.
+pub fn parse_domifaddr(s: &str) -> Vec<GuestInterface> {
+    let mut ifaces: Vec<GuestInterface> = Vec::new();
+    let mut in_body = false;
+
+    for line in s.lines() {
+        let l = line.trim();
+        if !in_body {
+            in_body = l.starts_with("---");
+            continue;
+        }
+        let cols = split_columns(l, 4);
+        if cols.len() < 4 {
+            continue;
+        }
+        let addr = cols[3].split('/').next().unwrap_or("").to_string();
+        let continuation = cols[0] == "-" && !ifaces.is_empty();
+        if !continuation {
+            ifaces.push(GuestInterface {
+                name: cols[0].to_string(),
+                mac: Some(cols[1].to_string()).filter(|m| m != "-"),
+                addresses: Vec::new(),
+            });
+        }
+        if !is_uninteresting_addr(&addr)
+            && let Some(iface) = ifaces.last_mut()
+        {
+            iface.addresses.push(addr);
         }
     }
 
-    DomInfo { max_memory_mb, used_memory_mb, cpu_time }
+    ifaces.retain(|i| !i.addresses.is_empty());
+    ifaces
+}
+
+/// One mounted guest filesystem from the `guest-get-fsinfo` agent RPC.
+/// Sizes are missing when the agent can't stat the mount (older agents,
+/// some Windows volumes).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GuestFilesystem {
+    pub mountpoint: String,
+    pub fs_type: String,
+    pub device: String,
+    pub used: Option<Bytes>,
+    pub total: Option<Bytes>,
+}
+
+impl GuestFilesystem {
+    /// Used space as a percentage of the total, if both are known.
+    pub fn used_percent(&self) -> Option<f64> {
+        match (self.used, self.total) {
+            (Some(used), Some(total)) if total.0 > 0 => Some(used.0 as f64 / total.0 as f64 * 100.0),
+            _ => None,
+        }
+    }
+}
+
+/// Read-only image filesystems (snap packages, mounted ISOs) are always 100%
+/// full and would trip any usage threshold, so they are not reported.
+fn is_image_fs(fs_type: &str) -> bool {
+    matches!(fs_type.to_ascii_lowercase().as_str(), "squashfs" | "iso9660" | "udf" | "cdfs")
+}
+
+/// Parse the `return` array of the `guest-get-fsinfo` agent RPC, sorted by
+/// mount point. Bind mounts reported twice are kept once.
+pub fn parse_guest_fsinfo(ret: &serde_json::Value) -> Vec<GuestFilesystem> {
+    let mut filesystems: Vec<GuestFilesystem> = Vec::new();
+    for fs in ret.as_array().into_iter().flatten() {
+        let str_field = |key: &str| fs.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        let fs = GuestFilesystem {
+            mountpoint: str_field("mountpoint"),
+            fs_type: str_field("type"),
+            device: str_field("name"),
+            used: fs.get("used-bytes").and_then(|v| v.as_u64()).map(Bytes),
+            total: fs.get("total-bytes").and_then(|v| v.as_u64()).map(Bytes),
+        };
+        if fs.mountpoint.is_empty() || is_image_fs(&fs.fs_type) {
+            continue;
+        }
+        if !filesystems.iter().any(|f| f.mountpoint == fs.mountpoint) {
+            filesystems.push(fs);
+        }
+    }
+    filesystems.sort_by(|a, b| a.mountpoint.cmp(&b.mountpoint));
+    filesystems
+}
+
+/// What a guest agent reports about itself via `guest-info`: its version and
+/// the commands it has enabled (disabled ones, e.g. blocked with
+/// `--block-rpcs`, are left out).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AgentInfo {
+    pub version: String,
+    pub commands: Vec<String>,
+}
+
+impl AgentInfo {
+    pub fn supports(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c == command)
+    }
+}
+
+/// Parse the `return` object of the `guest-info` agent RPC.
+pub fn parse_guest_info(ret: &serde_json::Value) -> AgentInfo {
+    let version = ret.get("version").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let commands = ret
+        .get("supported_commands")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|c| c.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true))
+        .filter_map(|c| c.get("name").and_then(|v| v.as_str()))
+        .map(|c| c.to_string())
+        .collect();
+    AgentInfo { version, commands }
+}
+
+/// Parse `virsh vol-list <pool>` output into volume paths. The path is the
+/// last column and always absolute, so it is located by its leading '/'.
+pub fn parse_vol_list_paths(s: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut in_body = false;
+
+    for line in s.lines() {
+        let l = line.trim();
+        if !in_body {
+            in_body = l.starts_with("---");
+            continue;
+        }
+        if let Some(idx) = l.find(" /") {
+            paths.push(l[idx + 1..].trim().to_string());
+        }
+    }
+
+    paths
+}
+
+/// Pick a display name from `/etc/os-release` contents: PRETTY_NAME, else
+/// NAME plus VERSION. Values may be single- or double-quoted.
+pub fn parse_os_release(s: &str) -> Option<String> {
+    let mut name = None;
+    let mut version = None;
+    for line in s.lines() {
+        let Some((key, val)) = line.trim().split_once('=') else { continue };
+        let val = val.trim().trim_matches('"').trim_matches('\'');
+        if val.is_empty() {
+            continue;
+        }
+        match key {
+            "PRETTY_NAME" => return Some(val.to_string()),
+            "NAME" => name = Some(val.to_string()),
+            "VERSION" => version = Some(val.to_string()),
+            _ => {}
+        }
+    }
+    match (name, version) {
+        (Some(n), Some(v)) => Some(format!("{} {}", n, v)),
+        (Some(n), None) => Some(n),
+        _ => None,
+    }
+}
+
+/// Extract the "OS Name:" value from Windows `systeminfo` output.
+pub fn parse_systeminfo_os(s: &str) -> Option<String> {
+    s.lines()
+        .filter_map(|l| l.trim().strip_prefix("OS Name:"))
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
 }
 
 /// Parse CPU time strings commonly seen in `virsh dominfo`:
@@ -62,21 +524,20 @@ pub fn parse_cpu_time_to_seconds(s: &str) -> Option<u64> {
         let mut total: u64 = 0;
         for token in s.split_whitespace() {
             let token = token.trim();
-            if token.ends_with('h') {
-                if let Ok(v) = token[..token.len() - 1].parse::<u64>() {
+            if let Some(num) = token.strip_suffix('h') {
+                if let Ok(v) = num.parse::<u64>() {
                     total = total.saturating_add(v.saturating_mul(3600));
                 } else {
                     return None;
                 }
-            } else if token.ends_with('m') {
-                if let Ok(v) = token[..token.len() - 1].parse::<u64>() {
+            } else if let Some(num) = token.strip_suffix('m') {
+                if let Ok(v) = num.parse::<u64>() {
                     total = total.saturating_add(v.saturating_mul(60));
                 } else {
                     return None;
                 }
-            } else if token.ends_with('s') {
+            } else if let Some(num) = token.strip_suffix('s') {
                 // allow fractional seconds like "154359.4s"
-                let num = &token[..token.len() - 1];
                 if let Ok(f) = num.parse::<f64>() {
                     total = total.saturating_add(f as u64);
                 } else {
@@ -95,40 +556,22 @@ pub fn parse_cpu_time_to_seconds(s: &str) -> Option<u64> {
     }
 
     // Single token cases: "154359.4s", "154359s", or plain number
-    let token = if s.ends_with('s') { &s[..s.len() - 1] } else { s };
+    let token = s.strip_suffix('s').unwrap_or(s);
     if let Ok(f) = token.parse::<f64>() {
         return Some(f as u64);
     }
     None
 }
 
-/// Convert an optional KiB value (as returned by many `virsh dominfo` outputs)
-/// into a human readable string using binary units (KiB, MiB, GiB).
-///
-/// Note: callers that parsed memory as MB/KiB should pass the correct unit.
-/// This helper assumes the numeric value is KiB (common for many libvirt setups).
-pub fn format_memory_kib(kib: Option<u64>) -> String {
-    match kib {
-        None => "(unknown)".to_string(),
-        Some(k) => {
-            // treat k as KiB; convert to bytes for unit scaling
-            let mut bytes = k.saturating_mul(1024u64);
-            const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
-            let mut unit = 0usize;
-            while bytes >= 1024 && unit < UNITS.len() - 1 {
-                bytes = bytes / 1024;
-                unit += 1;
-            }
-            if unit >= 2 {
-                // show one decimal for MiB+ for readability
-                let denom = 1024u64.pow(unit as u32);
-                let value = (k as f64 * 1024.0) / (denom as f64);
-                format!("{:.1} {}", value, UNITS[unit])
-            } else {
-                format!("{} {}", bytes, UNITS[unit])
-            }
-        }
+/// Like `parse_cpu_time_to_seconds`, but keeps the fractional part of the
+/// single-value form virsh prints ("154359.4s"), which matters when two
+/// samples a second apart are subtracted.
+pub fn parse_cpu_time_to_secs_f64(s: &str) -> Option<f64> {
+    let s = s.trim();
+    if let Ok(f) = s.strip_suffix('s').unwrap_or(s).parse::<f64>() {
+        return Some(f);
     }
+    parse_cpu_time_to_seconds(s).map(|secs| secs as f64)
 }
 
 /// Format seconds into a compact human string: "1d 2h 3m 4s" but omit zero units.
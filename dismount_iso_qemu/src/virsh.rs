@@ -1,57 +1,163 @@
 // src/virsh.rs
-use std::process::Command;
-use std::io;
+use std::sync::Arc;
 use serde_json::Value;
+use crate::error::VirshError;
+use crate::runner::{CommandRunner, SystemRunner};
+use crate::utils::{self, DomainState};
 
-/// Simple wrapper to call `virsh qemu-agent-command` and return parsed JSON.
-pub fn virsh_qemu_agent(vm: &str, payload: &str, timeout_secs: u64) -> io::Result<Value> {
-    let out = Command::new("virsh")
-        .args(["qemu-agent-command", "--timeout", &timeout_secs.to_string(), vm, payload])
-        .output()?;
-    if !out.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("virsh qemu-agent-command failed: {}", String::from_utf8_lossy(&out.stderr)),
-        ));
-    }
-    let s = String::from_utf8_lossy(&out.stdout);
-    let json: Value = serde_json::from_str(&s)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("json parse: {}", e)))?;
-    Ok(json)
+/// Which definition a `virsh change-media` call touches: whatever is current
+/// (live if running, config otherwise), the running domain, its persistent
+/// configuration, or both at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaScope {
+    Current,
+    Live,
+    Config,
+    Both,
 }
 
-/// Return VM names from `virsh list --all --name`.
-/// Trims empty lines and returns Vec<String>.
-pub fn list_vms() -> io::Result<Vec<String>> {
-    let out = Command::new("virsh")
-        .args(["list", "--all", "--name"])
-        .output()?;
-    if !out.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("virsh list failed: {}", String::from_utf8_lossy(&out.stderr)),
-        ));
-    }
-    let s = String::from_utf8_lossy(&out.stdout);
-    let vms: Vec<String> = s
-        .lines()
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
-        .map(|l| l.to_string())
-        .collect();
-    Ok(vms)
+impl MediaScope {
+    fn flags(self) -> &'static [&'static str] {
+        match self {
+            MediaScope::Current => &[],
+            MediaScope::Live => &["--live"],
+            MediaScope::Config => &["--config"],
+            MediaScope::Both => &["--live", "--config"],
+        }
+    }
+}
+
+/// Thin client around the `virsh` binary bound to one libvirt connection.
+/// Every command is run as `virsh -c <uri> ...`, so the tool can target a
+/// remote host (e.g. `qemu+ssh://root@host/system`) as well as the local one.
+/// Commands are executed through a `CommandRunner` so tests can swap in
+/// recorded outputs.
+#[derive(Clone)]
+pub struct Virsh {
+    uri: String,
+    runner: Arc<dyn CommandRunner>,
 }
 
-/// Return the raw `virsh dominfo <vm>` output as a String.
-pub fn dominfo_raw(vm: &str) -> io::Result<String> {
-    let out = Command::new("virsh")
-        .args(["dominfo", vm])
-        .output()?;
-    if !out.status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("virsh dominfo failed: {}", String::from_utf8_lossy(&out.stderr)),
-        ));
-    }
-    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+impl Virsh {
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self::with_runner(uri, Arc::new(SystemRunner))
+    }
+
+    pub fn with_runner(uri: impl Into<String>, runner: Arc<dyn CommandRunner>) -> Self {
+        Self { uri: uri.into(), runner }
+    }
+
+    /// The libvirt connection URI passed to every command.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// True when the URI has no host part (`qemu:///system`, `qemu+unix:///session`),
+    /// i.e. paths reported by libvirt refer to this machine's filesystem.
+    pub fn is_local(&self) -> bool {
+        match self.uri.split_once("://") {
+            Some((_, rest)) => rest.starts_with('/'),
+            None => true,
+        }
+    }
+
+    /// Run `virsh -c <uri> <args>` and return stdout, classifying a spawn
+    /// failure or non-zero exit into a `VirshError`. `what` names the
+    /// subcommand in the message.
+    fn run(&self, args: &[&str], what: &str) -> Result<String, VirshError> {
+        let mut full = vec!["-c", self.uri.as_str()];
+        full.extend_from_slice(args);
+        let out = self.runner.run(&full).map_err(|e| VirshError::from_spawn(&e))?;
+        if !out.success {
+            return Err(VirshError::from_stderr(what, &out.stderr));
+        }
+        Ok(out.stdout)
+    }
+
+    /// Call `virsh qemu-agent-command` and return the parsed JSON reply.
+    pub fn qemu_agent_command(&self, vm: &str, payload: &str, timeout_secs: u64) -> Result<Value, VirshError> {
+        let s = self.run(
+            &["qemu-agent-command", "--timeout", &timeout_secs.to_string(), vm, payload],
+            "qemu-agent-command",
+        )?;
+        let json: Value = serde_json::from_str(&s)
+            .map_err(|e| VirshError::Parse(format!("qemu-agent-command reply: {}", e)))?;
+        Ok(json)
+    }
+
+    /// Return VM names from `virsh list --all --name`.
+    /// Trims empty lines and returns Vec<String>.
+    pub fn list_vms(&self) -> Result<Vec<String>, VirshError> {
+        let s = self.run(&["list", "--all", "--name"], "list")?;
+        let vms: Vec<String> = s
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect();
+        Ok(vms)
+    }
+
+    /// Return every domain with its state from `virsh list --all`.
+    pub fn list_domains(&self) -> Result<Vec<(String, Option<DomainState>)>, VirshError> {
+        let s = self.run(&["list", "--all"], "list")?;
+        Ok(utils::parse_list_all(&s))
+    }
+
+    /// Return the domain UUID from `virsh domuuid <vm>`.
+    pub fn domuuid(&self, vm: &str) -> Result<String, VirshError> {
+        Ok(self.run(&["domuuid", vm], "domuuid")?.trim().to_string())
+    }
+
+    /// Return the raw `virsh dominfo <vm>` output as a String.
+    pub fn dominfo_raw(&self, vm: &str) -> Result<String, VirshError> {
+        self.run(&["dominfo", vm], "dominfo")
+    }
+
+    /// Return the raw `virsh domstats` output for every domain in one call
+    /// (state, CPU, balloon, vCPU, interface and block groups).
+    pub fn domstats_raw(&self) -> Result<String, VirshError> {
+        self.run(
+            &["domstats", "--state", "--cpu-total", "--balloon", "--vcpu", "--interface", "--block"],
+            "domstats",
+        )
+    }
+
+    /// Return the raw `virsh domblklist --details <vm>` output as a String.
+    pub fn domblklist_raw(&self, vm: &str) -> Result<String, VirshError> {
+        self.run(&["domblklist", "--details", vm], "domblklist")
+    }
+
+    /// Return the raw `virsh domifaddr <vm> --source <source>` output, where
+    /// `source` is `agent`, `lease` (libvirt DHCP) or `arp` (host ARP table).
+    pub fn domifaddr_raw(&self, vm: &str, source: &str) -> Result<String, VirshError> {
+        self.run(&["domifaddr", vm, "--source", source], "domifaddr")
+    }
+
+    /// Return the raw `virsh vol-list <pool>` output as a String.
+    pub fn vol_list_raw(&self, pool: &str) -> Result<String, VirshError> {
+        self.run(&["vol-list", pool], "vol-list")
+    }
+
+    /// Return the raw `virsh dumpxml <vm>` domain definition as a String.
+    pub fn dumpxml_raw(&self, vm: &str) -> Result<String, VirshError> {
+        self.run(&["dumpxml", vm], "dumpxml")
+    }
+
+    /// Attach `source` to the CD-ROM `target` of `vm` with `virsh change-media`.
+    /// Uses `--insert` for an empty tray and `--update` when `replace` is set, so
+    /// media already in the drive is swapped rather than rejected.
+    pub fn change_media_insert(&self, vm: &str, target: &str, source: &str, replace: bool) -> Result<(), VirshError> {
+        let action = if replace { "--update" } else { "--insert" };
+        self.run(&["change-media", vm, target, source, action], "change-media")?;
+        Ok(())
+    }
+
+    /// Eject whatever is in the CD-ROM `target` of `vm` with `virsh change-media --eject`.
+    pub fn change_media_eject(&self, vm: &str, target: &str, scope: MediaScope) -> Result<(), VirshError> {
+        let mut args = vec!["change-media", vm, target, "--eject"];
+        args.extend_from_slice(scope.flags());
+        self.run(&args, "change-media")?;
+        Ok(())
+    }
 }
@@ -0,0 +1,130 @@
+// src/watch.rs
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use crate::output::truncate;
+use crate::probe::ProbeManager;
+use crate::scan::{self, StateFilter, VmStatus};
+use crate::utils::{self, Bytes};
+
+const NAME_WIDTH: usize = 20;
+const OS_WIDTH: usize = 30;
+const MEM_WIDTH: usize = 12;
+const DELTA_WIDTH: usize = 12;
+const CPU_WIDTH: usize = 16;
+
+/// ANSI: cursor home / clear to end of line / clear to end of screen.
+const HOME: &str = "\x1b[H";
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+
+/// Top-style view: a background thread re-scans every `interval` and sends
+/// the rows over a channel; this thread owns `out` and redraws the table in
+/// place, so output never interleaves. Runs until interrupted or a write to
+/// `out` fails (e.g. stdout went away).
+pub fn watch(
+    mgr: &ProbeManager,
+    interval: Duration,
+    workers: usize,
+    filter: Option<StateFilter>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel::<anyhow::Result<Vec<VmStatus>>>();
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            loop {
+                let rows = scan::list_vms(mgr, filter).map(|vms| scan::scan_vms_with_cpu(mgr, &vms, workers));
+                if tx.send(rows).is_err() {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+        });
+        // `draw` owns the receiver, so a write error drops it and the
+        // producer's next send fails instead of keeping the scope alive.
+        draw(mgr, interval, rx, out)
+    })
+}
+
+fn draw(
+    mgr: &ProbeManager,
+    interval: Duration,
+    rx: mpsc::Receiver<anyhow::Result<Vec<VmStatus>>>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut prev: HashMap<String, VmStatus> = HashMap::new();
+    write!(out, "\x1b[2J")?;
+    for (n, msg) in rx.iter().enumerate() {
+        write!(out, "{}", HOME)?;
+        writeln!(
+            out,
+            "{} - every {}s - refresh #{}{}",
+            mgr.virsh().uri(),
+            interval.as_secs(),
+            n + 1,
+            CLEAR_LINE
+        )?;
+        writeln!(out, "{}", CLEAR_LINE)?;
+        match msg {
+            Ok(rows) => {
+                render_frame(&rows, &prev, out)?;
+                prev = rows.into_iter().map(|r| (r.name.clone(), r)).collect();
+            }
+            Err(e) => writeln!(out, "Failed to list VMs via virsh: {}{}", e, CLEAR_LINE)?,
+        }
+        write!(out, "{}", CLEAR_BELOW)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Write one frame of the watch table. Deltas are against `prev`, the rows of
+/// the previous refresh; VMs not seen before show `-`.
+pub fn render_frame(rows: &[VmStatus], prev: &HashMap<String, VmStatus>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:NAME_WIDTH$} {:OS_WIDTH$} {:>MEM_WIDTH$} {:>DELTA_WIDTH$} {:>CPU_WIDTH$} {:>DELTA_WIDTH$} {:>6}{}",
+        "VM", "OS", "Mem used", "dMem", "CPU time", "dCPU", "CPU %", CLEAR_LINE
+    )?;
+    for row in rows {
+        let before = prev.get(&row.name);
+        let os = row.os.as_deref().unwrap_or("(unknown)");
+        let cpu = row
+            .cpu_time_seconds
+            .map(utils::format_seconds_dhms)
+            .unwrap_or_else(|| "-".to_string());
+        let d_mem = match (before.and_then(|b| b.memory_used_bytes), row.memory_used_bytes) {
+            (Some(a), Some(b)) => signed(b.0 as i64 - a.0 as i64, |d| Bytes(d).to_string()),
+            _ => "-".to_string(),
+        };
+        let d_cpu = match (before.and_then(|b| b.cpu_time_seconds), row.cpu_time_seconds) {
+            (Some(a), Some(b)) => signed(b as i64 - a as i64, utils::format_seconds_dhms),
+            _ => "-".to_string(),
+        };
+        writeln!(
+            out,
+            "{:NAME_WIDTH$} {:OS_WIDTH$} {:>MEM_WIDTH$} {:>DELTA_WIDTH$} {:>CPU_WIDTH$} {:>DELTA_WIDTH$} {:>6}{}",
+            truncate(&row.name, NAME_WIDTH),
+            truncate(os, OS_WIDTH),
+            row.memory_used_bytes.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string()),
+            d_mem,
+            cpu,
+            d_cpu,
+            row.cpu_percent.map(|p| format!("{:.1}%", p)).unwrap_or_else(|| "-".to_string()),
+            CLEAR_LINE
+        )?;
+    }
+    Ok(())
+}
+
+/// Format a delta with an explicit sign; no change prints `0`.
+fn signed(delta: i64, fmt: impl Fn(u64) -> String) -> String {
+    match delta {
+        0 => "0".to_string(),
+        d if d > 0 => format!("+{}", fmt(d as u64)),
+        d => format!("-{}", fmt(d.unsigned_abs())),
+    }
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use dismount_iso_qemu::agent::{AgentTransport, UnixSocketAgent};
+use dismount_iso_qemu::error::VirshError;
+use dismount_iso_qemu::runner::FixtureRunner;
+
+use common::manager_with_calls;
+
+/// Serve a fake guest agent on `dir/qga.sock`. Each connection first gets a
+/// stale reply and a garbage line (as if a previous client hung up
+/// mid-exchange), then `guest-sync` is echoed and other commands are answered
+/// by `reply`. Returning `None` leaves the command unanswered.
+fn fake_agent(dir: &tempfile::TempDir, reply: fn(&str) -> Option<Value>) -> PathBuf {
+    let path = dir.path().join("qga.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            writeln!(stream, r#"{{"return": 7}}"#).unwrap();
+            stream.write_all(b"\xff garbage\n").unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                let req: Value = serde_json::from_str(&line).unwrap();
+                let cmd = req["execute"].as_str().unwrap_or("");
+                let resp = match cmd {
+                    "guest-sync" => Some(json!({ "return": req["arguments"]["id"] })),
+                    _ => reply(cmd),
+                };
+                if let Some(resp) = resp {
+                    writeln!(stream, "{}", resp).unwrap();
+                }
+            }
+        }
+    });
+    path
+}
+
+#[test]
+fn probe_manager_uses_the_socket_instead_of_virsh() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = fake_agent(&dir, |cmd| match cmd {
+        "guest-get-osinfo" => Some(json!({ "return": { "pretty-name": "Debian GNU/Linux 12 (bookworm)" } })),
+        _ => Some(json!({ "error": { "class": "CommandNotFound", "desc": "not supported" } })),
+    });
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new());
+    let mgr = mgr.with_agent_socket("web01", &sock);
+
+    assert_eq!(mgr.get_os("web01").unwrap().as_deref(), Some("Debian GNU/Linux 12 (bookworm)"));
+    assert!(runner.calls().is_empty(), "virsh was called: {:?}", runner.calls());
+}
+
+#[test]
+fn agent_errors_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = fake_agent(&dir, |_| Some(json!({ "error": { "class": "GenericError", "desc": "boom" } })));
+
+    let err = UnixSocketAgent::new(sock).agent_command("web01", r#"{"execute":"guest-info"}"#, 2).unwrap_err();
+    assert_eq!(err, VirshError::Agent("guest agent error: boom".to_string()));
+}
+
+#[test]
+fn silent_agent_times_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = fake_agent(&dir, |_| None);
+
+    let started = Instant::now();
+    let err = UnixSocketAgent::new(sock).agent_command("web01", r#"{"execute":"guest-info"}"#, 1).unwrap_err();
+    assert!(matches!(err, VirshError::Timeout(_)), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use dismount_iso_qemu::commands::{self, Command, RunOptions, EXIT_NOT_FOUND, EXIT_OK};
+use dismount_iso_qemu::output::Format;
+use dismount_iso_qemu::runner::FixtureRunner;
+
+use common::{manager, manager_with_calls};
+
+const OPTS: RunOptions = RunOptions { workers: 1, format: Format::Table, state: None };
+const BLKLIST: &str = "domblklist --details vm1";
+
+#[test]
+fn eject_all_loaded_drives_with_scope_flags() {
+    let runner = FixtureRunner::new()
+        .ok(BLKLIST, include_str!("fixtures/domblklist_details.txt"))
+        .ok("change-media vm1 sda --eject --live --config", "Successfully ejected media.\n");
+    let (mgr, runner) = manager_with_calls(runner);
+
+    let cmd = Command::Eject { vm: "vm1".into(), target: None, live: true, config: true };
+    assert_eq!(commands::run(cmd, &mgr, &OPTS), EXIT_OK);
+    assert!(!runner.calls().iter().any(|c| c.contains("sdb")), "empty drive sdb must not be touched");
+    assert_eq!(runner.calls().last().unwrap(), "-c qemu:///system change-media vm1 sda --eject --live --config");
+}
+
+#[test]
+fn eject_unknown_target_is_not_found() {
+    let runner = FixtureRunner::new().ok(BLKLIST, include_str!("fixtures/domblklist_details.txt"));
+    let mgr = manager(runner);
+
+    let cmd = Command::Eject { vm: "vm1".into(), target: Some("hdc".into()), live: false, config: false };
+    assert_eq!(commands::run(cmd, &mgr, &OPTS), EXIT_NOT_FOUND);
+}
+
+#[test]
+fn insert_uses_first_empty_drive() {
+    let runner = FixtureRunner::new()
+        .ok(BLKLIST, include_str!("fixtures/domblklist_details.txt"))
+        .ok("change-media vm1 sdb /isos/debian.iso --insert", "Successfully inserted media.\n");
+    let mgr = manager(runner);
+
+    let cmd = Command::Insert { vm: "vm1".into(), iso: "/isos/debian.iso".into(), target: None };
+    assert_eq!(commands::run(cmd, &mgr, &OPTS), EXIT_OK);
+}
+
+#[test]
+fn insert_replaces_media_on_named_target() {
+    let runner = FixtureRunner::new()
+        .ok(BLKLIST, include_str!("fixtures/domblklist_details.txt"))
+        .ok("change-media vm1 sda /isos/debian.iso --update", "Successfully updated media.\n");
+    let mgr = manager(runner);
+
+    let cmd = Command::Insert { vm: "vm1".into(), iso: "/isos/debian.iso".into(), target: Some("sda".into()) };
+    assert_eq!(commands::run(cmd, &mgr, &OPTS), EXIT_OK);
+}
+
+#[test]
+fn os_unknown_exits_not_found() {
+    let mgr = manager(FixtureRunner::new());
+
+    assert_eq!(commands::run(Command::Os { vm: "vm1".into() }, &mgr, &OPTS), EXIT_NOT_FOUND);
+}
@@ -0,0 +1,26 @@
+//! Setup shared by the integration tests.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dismount_iso_qemu::probe::{CacheTtls, ProbeManager};
+use dismount_iso_qemu::runner::{CommandRunner, FixtureRunner};
+use dismount_iso_qemu::virsh::Virsh;
+
+/// A manager for `qemu:///system` on `runner`, with a 5 s agent timeout and
+/// the default TTLs.
+pub fn manager(runner: impl CommandRunner + 'static) -> ProbeManager {
+    with_runner(Arc::new(runner))
+}
+
+/// Like [`manager`], also handing back the runner so tests can check `calls()`.
+pub fn manager_with_calls(runner: FixtureRunner) -> (ProbeManager, Arc<FixtureRunner>) {
+    let runner = Arc::new(runner);
+    (with_runner(runner.clone()), runner)
+}
+
+fn with_runner(runner: Arc<dyn CommandRunner>) -> ProbeManager {
+    let virsh = Virsh::with_runner("qemu:///system", runner);
+    ProbeManager::with_virsh(virsh, Duration::from_secs(5), CacheTtls::default()).unwrap()
+}
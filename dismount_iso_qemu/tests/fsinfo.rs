@@ -0,0 +1,63 @@
+mod common;
+
+use dismount_iso_qemu::fsinfo;
+use dismount_iso_qemu::runner::FixtureRunner;
+
+use common::manager_with_calls;
+
+const FSINFO: &str = r#"qemu-agent-command --timeout 5 web01 {"execute":"guest-get-fsinfo"}"#;
+
+fn report(rows: &[fsinfo::VmFilesystems], threshold: Option<u8>) -> String {
+    let mut out = Vec::new();
+    fsinfo::print_fs_table(rows, threshold, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn report_flags_filesystems_over_threshold() {
+    let (mgr, _) = manager_with_calls(
+        FixtureRunner::new()
+            .ok("list --all", " Id   Name    State\n----------------------------\n 1    web01   running\n -    old     shut off\n")
+            .ok(FSINFO, include_str!("fixtures/guest_get_fsinfo.json")),
+    );
+    mgr.list_domains().unwrap();
+    let rows = fsinfo::scan_filesystems(&mgr, &["web01".to_string(), "old".to_string()], 2);
+    let out = report(&rows, Some(90));
+    let lines: Vec<&str> = out.lines().collect();
+
+    assert!(lines[1].starts_with("web01                /                        ext4"), "{}", out);
+    assert!(lines[1].contains("19.6 GiB") && lines[1].ends_with("92%  [FULL]"), "{}", out);
+    assert!(lines[3].contains("/srv") && lines[3].ends_with(" 2%"), "{}", out);
+    assert_eq!(lines[4], "old                  (domain is shut off)");
+    assert_eq!(lines.last(), Some(&"1 guest(s) above 90% full: web01"));
+
+    assert!(!report(&rows, None).contains("[FULL]"));
+    assert!(report(&rows, Some(95)).ends_with("No guest is above 95% full.\n"));
+}
+
+#[test]
+fn filesystems_are_cached_and_failures_remembered() {
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new().ok(FSINFO, include_str!("fixtures/guest_get_fsinfo.json")));
+    assert_eq!(mgr.get_filesystems("web01").unwrap().unwrap().len(), 3);
+    mgr.get_filesystems("web01").unwrap();
+    // guest-info (unanswered, so capabilities are unknown) + guest-get-fsinfo
+    assert_eq!(runner.calls().len(), 2);
+
+    let (mgr, _) = manager_with_calls(FixtureRunner::new());
+    let first = fsinfo::scan_filesystems(&mgr, &["web01".to_string()], 1);
+    assert!(first[0].error.is_some());
+    let second = fsinfo::scan_filesystems(&mgr, &["web01".to_string()], 1);
+    assert_eq!(second[0].error.as_deref(), Some("guest agent unavailable (cached)"));
+}
+
+#[test]
+fn unsupported_fsinfo_is_not_attempted() {
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new().ok(
+        r#"qemu-agent-command --timeout 5 web01 {"execute":"guest-info"}"#,
+        include_str!("fixtures/guest_info.json"),
+    ));
+    let err = mgr.get_filesystems("web01").unwrap_err();
+
+    assert!(err.to_string().contains("does not support guest-get-fsinfo"), "{}", err);
+    assert_eq!(runner.calls().len(), 1);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use dismount_iso_qemu::metrics;
+use dismount_iso_qemu::runner::FixtureRunner;
+
+use common::manager_with_calls;
+
+#[test]
+fn exports_gauges_and_skips_unknown_values() {
+    // vm1 answers everything; vm2 is shut off with failing dominfo/domblklist.
+    let runner = FixtureRunner::new()
+        .ok("list --all", " Id   Name   State\n-----------------------\n 1    vm1    running\n -    vm2    shut off\n")
+        .ok(r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-info"}"#, include_str!("fixtures/guest_info.json"))
+        .ok(
+            r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-get-osinfo"}"#,
+            include_str!("fixtures/guest_get_osinfo.json"),
+        )
+        .ok("dominfo vm1", include_str!("fixtures/dominfo_running.txt"))
+        .ok("domblklist --details vm1", include_str!("fixtures/domblklist_details.txt"))
+        .fail("dominfo vm2", "error: failed to get domain 'vm2'");
+    let (mgr, runner) = manager_with_calls(runner);
+
+    let text = metrics::render(&metrics::collect(&mgr, 2, None).unwrap());
+    let samples: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+
+    assert_eq!(
+        samples,
+        vec![
+            r#"dismount_iso_qemu_memory_used_bytes{vm="vm1"} 8589934592"#,
+            r#"dismount_iso_qemu_memory_max_bytes{vm="vm1"} 8589934592"#,
+            r#"dismount_iso_qemu_cpu_seconds_total{vm="vm1"} 122705"#,
+            r#"dismount_iso_qemu_agent_up{vm="vm1"} 1"#,
+            r#"dismount_iso_qemu_agent_up{vm="vm2"} 0"#,
+            r#"dismount_iso_qemu_running{vm="vm1"} 1"#,
+            r#"dismount_iso_qemu_running{vm="vm2"} 0"#,
+            r#"dismount_iso_qemu_attached_isos{vm="vm1"} 1"#,
+        ]
+    );
+    assert!(text.contains("# TYPE dismount_iso_qemu_attached_isos gauge"));
+    assert!(text.contains("# TYPE dismount_iso_qemu_cpu_seconds_total counter"));
+    assert!(!runner.calls().iter().any(|c| c.contains("qemu-agent-command --timeout 5 vm2")), "shut-off guest probed");
+}
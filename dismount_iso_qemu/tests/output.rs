@@ -0,0 +1,93 @@
+use dismount_iso_qemu::output::{self, Format};
+use dismount_iso_qemu::scan::VmStatus;
+use dismount_iso_qemu::utils::{self, DomainState, GuestInterface};
+
+fn rows() -> Vec<VmStatus> {
+    let info = utils::parse_dominfo(include_str!("fixtures/dominfo_running.txt"));
+    let mut pinhole = VmStatus::new("pinhole_new", Ok(Some("Ubuntu 18.04.6 LTS".into())), &info);
+    pinhole.cpu_percent = Some(12.5);
+    pinhole.state = Some(DomainState::Running);
+    pinhole.agent_version = Some("8.2.2".into());
+    pinhole.interfaces = vec![GuestInterface {
+        name: "enp1s0".into(),
+        mac: Some("52:54:00:a1:b2:c3".into()),
+        addresses: vec!["2001:db8::45".into(), "192.168.122.45".into()],
+    }];
+    vec![
+        pinhole,
+        VmStatus::new(
+            "win11_lab",
+            Ok(Some("Microsoft Windows 11 Pro, \"Insider\" build 26100.1000 (x64 multi-session)".into())),
+            &utils::DomInfo::default(),
+        ),
+    ]
+}
+
+fn render(format: Format) -> String {
+    let mut out = Vec::new();
+    output::render(&rows(), format, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn table_truncates_long_os_to_keep_alignment() {
+    let table = render(Format::Table);
+    let lines: Vec<&str> = table.lines().collect();
+
+    assert!(lines[1].starts_with("pinhole_new          running     Ubuntu 18.04.6 LTS"));
+    assert!(lines[2].starts_with("win11_lab            -           Microsoft"));
+    assert!(lines[1].contains("8.0 GiB / 8.0 GiB"));
+    assert!(lines[1].contains(" 8.2.2    192.168.122.45 +1    52:54:00:a1:b2:c3 "), "{:?}", lines[1]);
+    assert!(lines[1].contains("1d 10h 5m 5s"));
+    assert!(lines[1].ends_with("    4  12.5%"), "{:?}", lines[1]);
+    assert!(lines[2].contains("Microsoft Windows 11 Pro, \"Insider\" bui…"));
+    let col = |line: &str, needle: &str| line[..line.find(needle).unwrap()].chars().count();
+    assert_eq!(col(lines[2], "(unknown)"), col(lines[1], "8.0 GiB"));
+}
+
+#[test]
+fn json_is_an_array_of_records() {
+    let json: serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+
+    assert_eq!(json[0]["name"], "pinhole_new");
+    assert_eq!(json[0]["memory_used_bytes"], 8_589_934_592u64);
+    assert_eq!(json[0]["cpu_time_seconds"], 122_705);
+    assert_eq!(json[0]["vcpus"], 4);
+    assert_eq!(json[0]["cpu_percent"], 12.5);
+    assert!(json[1]["cpu_percent"].is_null());
+    assert!(json[1]["memory_max_bytes"].is_null());
+    assert!(json[0].get("os_error").is_none());
+    assert_eq!(json[0]["state"], "running");
+    assert_eq!(json[0]["agent_version"], "8.2.2");
+    assert!(json[1]["agent_version"].is_null());
+    assert_eq!(json[0]["interfaces"][0]["mac"], "52:54:00:a1:b2:c3");
+    assert_eq!(json[0]["interfaces"][0]["addresses"][1], "192.168.122.45");
+}
+
+#[test]
+fn csv_quotes_fields_with_commas_and_quotes() {
+    let csv = render(Format::Csv);
+    let lines: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(lines[0], "name,state,os,agent_version,ip_addresses,macs,memory_used_bytes,memory_max_bytes,cpu_time_seconds,vcpus,cpu_percent");
+    assert_eq!(lines[1], "pinhole_new,running,Ubuntu 18.04.6 LTS,8.2.2,2001:db8::45 192.168.122.45,52:54:00:a1:b2:c3,8589934592,8589934592,122705,4,12.5");
+    assert_eq!(
+        lines[2],
+        "win11_lab,,\"Microsoft Windows 11 Pro, \"\"Insider\"\" build 26100.1000 (x64 multi-session)\",,,,,,,,"
+    );
+}
+
+#[test]
+fn json_and_csv_spell_the_state_alike() {
+    let mut row = VmStatus::new("fs00", Ok(None), &utils::DomInfo::default());
+    row.state = Some(DomainState::ShutOff);
+    let mut json = Vec::new();
+    output::render(std::slice::from_ref(&row), Format::Json, &mut json).unwrap();
+    let mut csv = Vec::new();
+    output::render(&[row], Format::Csv, &mut csv).unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json[0]["state"], "shut_off");
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().nth(1).unwrap().split(',').nth(1), Some("shut_off"));
+}
@@ -0,0 +1,305 @@
+use dismount_iso_qemu::cli;
+use dismount_iso_qemu::domxml::{self, Firmware};
+use dismount_iso_qemu::error::VirshError;
+use dismount_iso_qemu::stats;
+use dismount_iso_qemu::utils::{self, Bytes, DomainState};
+
+#[test]
+fn parse_dominfo_running_guest() {
+    let info = utils::parse_dominfo(include_str!("fixtures/dominfo_running.txt"));
+    assert_eq!(info.max_memory, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(info.used_memory, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(info.cpu_time.as_deref(), Some("122705.3s"));
+    assert_eq!(info.vcpus, Some(4));
+    assert_eq!(utils::parse_cpu_time_to_secs_f64("122705.3s"), Some(122705.3));
+}
+
+#[test]
+fn parse_dominfo_shut_off_guest_has_no_cpu_time() {
+    let info = utils::parse_dominfo(include_str!("fixtures/dominfo_shutoff.txt"));
+    assert_eq!(info.max_memory, Some(Bytes::from_kib(4_194_304)));
+    assert_eq!(info.cpu_time, None);
+}
+
+#[test]
+fn parse_dominfo_empty_output() {
+    let info = utils::parse_dominfo("");
+    assert_eq!(info.max_memory, None);
+    assert_eq!(info.used_memory, None);
+    assert_eq!(info.cpu_time, None);
+}
+
+#[test]
+fn cpu_time_formats() {
+    assert_eq!(utils::parse_cpu_time_to_seconds("613h 33m 33s"), Some(2_208_813));
+    assert_eq!(utils::parse_cpu_time_to_seconds("154359.4s"), Some(154_359));
+    assert_eq!(utils::parse_cpu_time_to_seconds("12345"), Some(12_345));
+    assert_eq!(utils::parse_cpu_time_to_seconds("soon"), None);
+    assert_eq!(utils::format_seconds_dhms(122_705), "1d 10h 5m 5s");
+}
+
+#[test]
+fn parse_domblklist_keeps_spaces_in_source() {
+    let devs = utils::parse_domblklist(include_str!("fixtures/domblklist_details.txt"));
+    assert_eq!(devs.len(), 3);
+    assert_eq!(devs[1].device, "cdrom");
+    assert_eq!(devs[1].target, "sda");
+    assert_eq!(devs[1].source.as_deref(), Some("/var/lib/libvirt/images/ubuntu 18.04.6 server.iso"));
+    assert_eq!(devs[2].source, None);
+}
+
+#[test]
+fn parse_os_release_prefers_pretty_name() {
+    let name = utils::parse_os_release(include_str!("fixtures/os_release_alpine.txt"));
+    assert_eq!(name.as_deref(), Some("Alpine Linux v3.19"));
+    assert_eq!(utils::parse_os_release("NAME='Foo'\nVERSION=\"1 (x)\"\n").as_deref(), Some("Foo 1 (x)"));
+    assert_eq!(utils::parse_os_release("ID=foo\n"), None);
+}
+
+#[test]
+fn parse_systeminfo_os_name() {
+    let out = "Host Name:                 WIN11-LAB\r\nOS Name:                   Microsoft Windows 11 Pro\r\nOS Version:                10.0.22631 N/A Build 22631\r\n";
+    assert_eq!(utils::parse_systeminfo_os(out).as_deref(), Some("Microsoft Windows 11 Pro"));
+}
+
+#[test]
+fn shell_args_keep_quoted_paths_together() {
+    assert_eq!(
+        cli::split_args(r#"insert web01 "/srv/iso/Debian 12.iso"  sdb"#),
+        vec!["insert", "web01", "/srv/iso/Debian 12.iso", "sdb"]
+    );
+    assert_eq!(cli::split_args("eject all ''"), vec!["eject", "all", ""]);
+    assert!(cli::split_args("   ").is_empty());
+}
+
+#[test]
+fn shell_eject_takes_the_subcommand_flags() {
+    let args = |line: &str| cli::eject_args(&cli::split_args(line));
+
+    assert_eq!(args("--target sda --live"), Some((Some("sda".into()), true, false)));
+    assert_eq!(args("--config"), Some((None, false, true)));
+    assert_eq!(args(""), Some((None, false, false)));
+    assert_eq!(args("--target"), None);
+    assert_eq!(args("--conifg"), None);
+    assert_eq!(args("sda"), None);
+}
+
+#[test]
+fn eject_all_selection_answers() {
+    assert_eq!(cli::parse_selection("y", 3), Some(vec![0, 1, 2]));
+    assert_eq!(cli::parse_selection(" YES ", 2), Some(vec![0, 1]));
+    assert_eq!(cli::parse_selection("", 3), Some(vec![]));
+    assert_eq!(cli::parse_selection("n", 3), Some(vec![]));
+    assert_eq!(cli::parse_selection("3, 1 3", 3), Some(vec![2, 0]));
+    assert_eq!(cli::parse_selection("4", 3), None);
+    assert_eq!(cli::parse_selection("0", 3), None);
+    assert_eq!(cli::parse_selection("sure", 3), None);
+}
+
+#[test]
+fn parse_domstats_running_and_inactive_domains() {
+    let doms = stats::parse_domstats(include_str!("fixtures/domstats.txt"));
+    assert_eq!(doms.len(), 2);
+
+    let pinhole = &doms[0];
+    assert_eq!(pinhole.name, "pinhole_new");
+    assert_eq!(pinhole.state, Some(1));
+    assert_eq!(pinhole.vcpu_current, Some(4));
+    assert_eq!(pinhole.block.len(), 2);
+    assert_eq!(pinhole.block[0].wr_bytes, 102_400_000);
+    assert_eq!(pinhole.block[1].name, "sda");
+    assert_eq!(pinhole.net[0].tx_pkts, 800);
+    let info = pinhole.to_dominfo();
+    assert_eq!(info.used_memory, Some(Bytes::from_kib(4_194_304)));
+    assert_eq!(info.cpu_time.as_deref().and_then(utils::parse_cpu_time_to_seconds), Some(122_705));
+
+    let fs00 = &doms[1];
+    assert_eq!(fs00.state, Some(5));
+    assert_eq!(fs00.cpu_time_ns, None);
+    assert!(fs00.block.is_empty());
+}
+
+#[test]
+fn parse_dominfo_german_labels_and_decimal_comma() {
+    let info = utils::parse_dominfo(include_str!("fixtures/dominfo_de.txt"));
+    assert_eq!(info.max_memory, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(info.used_memory, Some(Bytes::from_kib(4_194_304)));
+    assert_eq!(info.cpu_time.as_deref(), Some("122705.3s"));
+    assert_eq!(info.vcpus, Some(4));
+}
+
+#[test]
+fn parse_dominfo_french_shut_off_guest() {
+    let info = utils::parse_dominfo(include_str!("fixtures/dominfo_fr.txt"));
+    assert_eq!(info.max_memory, Some(Bytes::from_kib(4_194_304)));
+    assert_eq!(info.used_memory, Some(Bytes::from_kib(2_097_152)));
+    assert_eq!(info.cpu_time, None);
+    assert_eq!(info.vcpus, Some(2));
+}
+
+#[test]
+fn byte_quantities_parse_units_and_format_binary() {
+    assert_eq!(Bytes::parse("8388608 KiB"), Some(Bytes(8 << 30)));
+    assert_eq!(Bytes::parse("512 MiB"), Some(Bytes(512 << 20)));
+    assert_eq!(Bytes::parse("4096 b"), Some(Bytes(4096)));
+    assert_eq!(Bytes::parse("42"), None);
+    assert_eq!(Bytes::parse("1 parsec"), None);
+    assert_eq!(Bytes(512).to_string(), "512 B");
+    assert_eq!(Bytes::from_kib(2).to_string(), "2 KiB");
+    assert_eq!(Bytes::from_kib(1536).to_string(), "1.5 MiB");
+    assert_eq!(Bytes::from_kib(8_388_608).to_string(), "8.0 GiB");
+}
+
+#[test]
+fn parse_list_all_keeps_multi_word_states() {
+    let doms = utils::parse_list_all(include_str!("fixtures/list_all.txt"));
+    assert_eq!(
+        doms,
+        vec![
+            ("pinhole_new".to_string(), Some(DomainState::Running)),
+            ("fs00".to_string(), Some(DomainState::Paused)),
+            ("win11_lab".to_string(), Some(DomainState::ShutOff)),
+        ]
+    );
+    assert_eq!(DomainState::ShutOff.to_string(), "shut off");
+}
+
+#[test]
+fn guest_interfaces_drop_loopback_and_link_local() {
+    let reply: serde_json::Value = serde_json::from_str(include_str!("fixtures/guest_network_interfaces.json")).unwrap();
+    let ifaces = utils::parse_guest_interfaces(&reply["return"]);
+
+    assert_eq!(ifaces.len(), 1);
+    assert_eq!(ifaces[0].name, "enp1s0");
+    assert_eq!(ifaces[0].mac.as_deref(), Some("52:54:00:a1:b2:c3"));
+    assert_eq!(ifaces[0].addresses, vec!["192.168.122.45", "2001:db8::45"]);
+}
+
+#[test]
+fn guest_fsinfo_skips_image_mounts_and_duplicates() {
+    let reply: serde_json::Value = serde_json::from_str(include_str!("fixtures/guest_get_fsinfo.json")).unwrap();
+    let filesystems = utils::parse_guest_fsinfo(&reply["return"]);
+
+    let mounts: Vec<&str> = filesystems.iter().map(|f| f.mountpoint.as_str()).collect();
+    assert_eq!(mounts, vec!["/", "/boot/efi", "/srv"]);
+    assert_eq!(filesystems[0].fs_type, "ext4");
+    assert_eq!(filesystems[0].total, Some(Bytes(21_003_583_488)));
+    assert_eq!(filesystems[0].used_percent().map(|p| p.round()), Some(92.0));
+    assert_eq!(utils::GuestFilesystem::default().used_percent(), None);
+}
+
+#[test]
+fn domifaddr_folds_continuation_rows() {
+    let ifaces = utils::parse_domifaddr(include_str!("fixtures/domifaddr_arp.txt"));
+
+    assert_eq!(ifaces.len(), 2);
+    assert_eq!(ifaces[0].addresses, vec!["192.168.122.45", "192.168.122.46"]);
+    assert_eq!(ifaces[1].mac.as_deref(), Some("52:54:00:d4:e5:f6"));
+    assert_eq!(ifaces[1].addresses, vec!["10.0.0.7"]);
+}
+
+#[test]
+fn virsh_errors_are_classified_from_stderr() {
+    let classify = |stderr: &str| VirshError::from_stderr("qemu-agent-command", stderr);
+
+    assert!(matches!(
+        classify("error: Guest agent is not responding: QEMU guest agent is not connected"),
+        VirshError::AgentNotConnected(_)
+    ));
+    assert!(matches!(
+        classify("error: failed to get domain 'nope'\n"),
+        VirshError::DomainNotFound(_)
+    ));
+    assert!(matches!(
+        classify("error: Requested operation is not valid: domain is not running"),
+        VirshError::DomainNotRunning(_)
+    ));
+    assert!(matches!(
+        classify("error: internal error: unable to execute QEMU agent command 'guest-get-fsinfo': Command guest-get-fsinfo has been disabled"),
+        VirshError::AgentCommandUnsupported(_)
+    ));
+    assert!(matches!(
+        classify("error: Timed out during operation: cannot acquire state change lock"),
+        VirshError::Timeout(_)
+    ));
+    assert!(matches!(
+        classify("error: failed to connect to the hypervisor\nerror: authentication failed: access denied by policy"),
+        VirshError::PermissionDenied(_)
+    ));
+    assert!(matches!(
+        classify("error: failed to connect to the hypervisor\nerror: Failed to connect socket to '/var/run/libvirt/libvirt-sock': No such file or directory"),
+        VirshError::ConnectionFailed(_)
+    ));
+
+    let other = VirshError::from_stderr("change-media", "error: something odd\n");
+    assert_eq!(other.to_string(), "virsh change-media failed: something odd");
+    assert_eq!(other.hint(), None);
+    assert!(!other.agent_unreachable());
+    assert!(matches!(
+        VirshError::from_stderr("change-media", "error: internal error: unable to execute QEMU command 'blockdev-change-medium': command not found"),
+        VirshError::Failed { .. }
+    ));
+
+    let missing = VirshError::from_spawn(&std::io::Error::from(std::io::ErrorKind::NotFound));
+    assert_eq!(missing, VirshError::NotInstalled);
+    assert!(missing.hint().is_some());
+}
+
+#[test]
+fn domain_xml_is_parsed_into_typed_fields() {
+    let dom = domxml::parse(include_str!("fixtures/dumpxml_q35_efi.xml")).unwrap();
+
+    assert_eq!(dom.name, "pinhole_new");
+    assert_eq!(dom.domain_type, "kvm");
+    assert_eq!(dom.memory, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(dom.current_memory, Some(Bytes::from_kib(4_194_304)));
+    assert_eq!((dom.vcpus, dom.current_vcpus), (Some(4), Some(2)));
+    assert_eq!(dom.machine.as_deref(), Some("pc-q35-8.2"));
+    assert_eq!(
+        dom.firmware,
+        Firmware::Efi { loader: Some("/usr/share/OVMF/OVMF_CODE_4M.ms.fd".into()), secure_boot: true }
+    );
+    assert!(dom.agent_channel);
+
+    let targets: Vec<&str> = dom.disks.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, vec!["vda", "vdb", "sda", "sdb"]);
+    assert_eq!(dom.disks[0].format.as_deref(), Some("qcow2"));
+    assert_eq!(dom.disks[1].source.as_deref(), Some("data/pinhole_srv"));
+    let cdroms: Vec<_> = dom.cdroms().collect();
+    assert_eq!(cdroms.len(), 2);
+    assert!(cdroms[0].readonly && cdroms[0].bus == "sata");
+    assert_eq!(cdroms[1].source, None);
+
+    assert_eq!(dom.interfaces.len(), 2);
+    assert_eq!(dom.interfaces[0].source.as_deref(), Some("default"));
+    assert_eq!(dom.interfaces[0].target.as_deref(), Some("vnet2"));
+    assert_eq!(dom.interfaces[1].model.as_deref(), Some("e1000e"));
+    assert_eq!(dom.interfaces[1].mac.as_deref(), Some("52:54:00:d4:e5:f6"));
+}
+
+#[test]
+fn minimal_domain_xml_defaults_to_bios() {
+    let dom = domxml::parse("<domain type='qemu'><name>tiny</name><memory unit='M'>512</memory><vcpu>1</vcpu></domain>").unwrap();
+
+    assert_eq!(dom.firmware, Firmware::Bios);
+    assert_eq!(dom.memory, Some(Bytes(512 << 20)));
+    assert_eq!(dom.current_vcpus, None);
+    assert!(dom.disks.is_empty() && !dom.agent_channel);
+    assert!(domxml::parse("<domain>").is_err());
+}
+
+#[test]
+fn domain_xml_memory_units_follow_libvirt() {
+    let memory = |unit: &str| {
+        domxml::parse(&format!("<domain><memory unit='{}'>2</memory></domain>", unit)).unwrap().memory
+    };
+
+    assert_eq!(memory("KB"), Some(Bytes(2_000)));
+    assert_eq!(memory("MB"), Some(Bytes(2_000_000)));
+    assert_eq!(memory("GB"), Some(Bytes(2_000_000_000)));
+    assert_eq!(memory("TB"), Some(Bytes(2_000_000_000_000)));
+    assert_eq!(memory("KiB"), Some(Bytes(2 << 10)));
+    assert_eq!(memory("G"), Some(Bytes(2 << 30)));
+    assert_eq!(memory("bytes"), Some(Bytes(2)));
+    assert_eq!(memory("furlongs"), None);
+}
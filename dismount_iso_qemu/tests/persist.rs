@@ -0,0 +1,120 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use dismount_iso_qemu::persist::{self, PersistedCache, PersistedEntry};
+use dismount_iso_qemu::runner::FixtureRunner;
+use dismount_iso_qemu::scan;
+
+use common::{manager, manager_with_calls};
+
+const UUID_A: &str = "6f1c2a0e-8b5d-4c47-9f0e-2d7b1a9c3e51";
+const UUID_B: &str = "0b9e8d3f-1a2c-4e5f-8d7c-6b5a4f3e2d1c";
+
+fn entry(uuid: &str, os: &str, age: Duration) -> PersistedEntry {
+    PersistedEntry { uuid: uuid.into(), os: os.into(), probed_at: persist::unix_secs(SystemTime::now() - age) }
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state/probe_cache.json");
+    let mut cache = PersistedCache::default();
+    cache.entries.insert("vm1".into(), entry(UUID_A, "Ubuntu 22.04.4 LTS", Duration::ZERO));
+
+    cache.save(&path).unwrap();
+
+    assert_eq!(PersistedCache::load(&path), cache);
+}
+
+#[test]
+fn merge_keeps_entries_of_domains_not_in_the_snapshot() {
+    let mut cache = PersistedCache::default();
+    cache.entries.insert("vm1".into(), entry(UUID_A, "Debian 11", Duration::from_secs(3600)));
+    cache.entries.insert("vm2".into(), entry(UUID_B, "Windows 11", Duration::from_secs(3600)));
+    let mut snapshot = PersistedCache::default();
+    snapshot.entries.insert("vm1".into(), entry(UUID_A, "Debian 12", Duration::ZERO));
+
+    cache.merge(snapshot);
+
+    assert_eq!(cache.entries["vm1"].os, "Debian 12");
+    assert_eq!(cache.entries["vm2"].os, "Windows 11");
+}
+
+#[test]
+fn load_tolerates_missing_and_corrupt_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("probe_cache.json");
+    assert!(PersistedCache::load(&path).entries.is_empty());
+
+    std::fs::write(&path, "{not json").unwrap();
+    assert!(PersistedCache::load(&path).entries.is_empty());
+}
+
+#[test]
+fn restore_drops_entries_whose_uuid_changed() {
+    let runner = FixtureRunner::new()
+        .ok("domuuid vm1", format!("{}\n", UUID_A))
+        .ok("domuuid vm2", format!("{}\n", UUID_A));
+    let (mgr, runner) = manager_with_calls(runner);
+    mgr.domain_uuid("vm1").unwrap();
+    mgr.domain_uuid("vm2").unwrap();
+
+    let mut persisted = PersistedCache::default();
+    persisted.entries.insert("vm1".into(), entry(UUID_A, "Debian 12", Duration::from_secs(10)));
+    persisted.entries.insert("vm2".into(), entry(UUID_B, "Windows 11", Duration::from_secs(10)));
+    let stale = mgr.restore_os(&persisted);
+
+    assert!(stale.is_empty());
+    let calls = runner.calls().len();
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("Debian 12"));
+    assert_eq!(runner.calls().len(), calls, "fresh restored entry should not be re-probed");
+    assert_eq!(mgr.get_os_or_stale("vm2").unwrap(), None, "re-created domain must not inherit the old OS");
+}
+
+#[test]
+fn stale_entries_are_shown_then_reported_for_refresh() {
+    let runner = FixtureRunner::new().ok("domuuid vm1", UUID_A);
+    let mgr = manager(runner);
+    mgr.domain_uuid("vm1").unwrap();
+
+    let mut persisted = PersistedCache::default();
+    persisted.entries.insert("vm1".into(), entry(UUID_A, "Debian 12", Duration::from_secs(3600)));
+
+    assert_eq!(mgr.restore_os(&persisted), vec!["vm1"]);
+    assert_eq!(mgr.get_os_or_stale("vm1").unwrap().as_deref(), Some("Debian 12"));
+    assert_eq!(mgr.snapshot_os().entries["vm1"].uuid, UUID_A);
+}
+
+#[test]
+fn startup_scan_leaves_agent_probes_to_the_background_refresh() {
+    let runner = FixtureRunner::new()
+        .ok("domuuid vm1", UUID_A)
+        .ok(r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-info"}"#, include_str!("fixtures/guest_info.json"))
+        .ok(r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-get-os"}"#, include_str!("fixtures/guest_get_os.json"))
+        .ok(
+            r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-network-get-interfaces"}"#,
+            include_str!("fixtures/guest_network_interfaces.json"),
+        );
+    let (mgr, runner) = manager_with_calls(runner);
+    mgr.domain_uuid("vm1").unwrap();
+    let mut persisted = PersistedCache::default();
+    persisted.entries.insert("vm1".into(), entry(UUID_A, "Debian 12", Duration::from_secs(3600)));
+    let stale = mgr.restore_os(&persisted);
+    let vms = vec!["vm1".to_string()];
+
+    let rows = scan::scan_vms(&mgr, &vms, 1, true);
+
+    assert_eq!(rows[0].os.as_deref(), Some("Debian 12"));
+    let probes = |runner: &FixtureRunner| {
+        runner.calls().iter().filter(|c| c.contains("qemu-agent-command") || c.contains("domifaddr")).count()
+    };
+    assert_eq!(probes(&runner), 0, "{:?}", runner.calls());
+
+    scan::refresh_stale(&mgr, &vms, &stale, 1);
+
+    assert_eq!(probes(&runner), 3, "{:?}", runner.calls());
+    assert_eq!(mgr.peek_os("vm1").as_deref(), Some("CentOS Linux 7 (Core)"));
+    assert!(mgr.peek_agent_info("vm1").is_some());
+    assert!(mgr.peek_interfaces("vm1").is_some_and(|ifaces| !ifaces.is_empty()));
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dismount_iso_qemu::runner::{CommandOutput, CommandRunner, FixtureRunner};
+use dismount_iso_qemu::scan;
+use dismount_iso_qemu::utils::Bytes;
+
+use common::{manager, manager_with_calls};
+
+/// Delays every agent call to stand in for a guest that sits on the timeout.
+struct SlowAgent(FixtureRunner, Duration);
+
+impl CommandRunner for SlowAgent {
+    fn run(&self, args: &[&str]) -> io::Result<CommandOutput> {
+        if args.contains(&"qemu-agent-command") {
+            thread::sleep(self.1);
+        }
+        self.0.run(args)
+    }
+}
+
+#[test]
+fn rows_keep_input_order() {
+    let runner = FixtureRunner::new()
+        .ok(r#"qemu-agent-command --timeout 5 b {"execute":"guest-get-osinfo"}"#, r#"{"return":{"pretty-name":"Debian 12"}}"#)
+        .ok("dominfo a", include_str!("fixtures/dominfo_running.txt"));
+    let mgr = manager(runner);
+    let vms: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+
+    let rows = scan::scan_vms(&mgr, &vms, 2, false);
+
+    let names: Vec<&str> = rows.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "c"]);
+    assert_eq!(rows[0].os, None);
+    assert_eq!(rows[0].memory_max_bytes, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(rows[0].cpu_time_seconds, Some(122_705));
+    assert_eq!(rows[1].os.as_deref(), Some("Debian 12"));
+    assert_eq!(rows[2].cpu_time_seconds, None);
+}
+
+#[test]
+fn slow_agents_are_probed_concurrently() {
+    let delay = Duration::from_millis(100);
+    let mgr = manager(SlowAgent(FixtureRunner::new(), delay));
+    let vms: Vec<String> = (0..8).map(|i| format!("vm{}", i)).collect();
+
+    let started = Instant::now();
+    let rows = scan::scan_vms(&mgr, &vms, 8, false);
+
+    assert_eq!(rows.len(), 8);
+    // Serially this is 8 VMs x 7 agent RPCs (guest-info, osinfo, os, three
+    // guest-exec tries, network interfaces) x 100 ms = 5.6 s.
+    assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+}
+
+#[test]
+fn cpu_percent_from_two_uncached_samples() {
+    let before = include_str!("fixtures/dominfo_running.txt");
+    let after = before.replace("122705.3s", "122707.3s");
+    let runner = FixtureRunner::new().ok("dominfo a", before).ok("dominfo a", after);
+    let (mgr, runner) = manager_with_calls(runner);
+
+    let rows = scan::scan_vms_with_cpu(&mgr, &["a".to_string()], 1);
+
+    // 2 s of CPU over ~1 s of wall time on 4 vCPUs is ~50%.
+    let pct = rows[0].cpu_percent.unwrap();
+    assert!(pct > 30.0 && pct <= 50.0, "{}", pct);
+    assert_eq!(rows[0].vcpus, Some(4));
+    let dominfo_calls = runner.calls().iter().filter(|c| c.ends_with("dominfo a")).count();
+    assert_eq!(dominfo_calls, 2, "both samples must bypass the cache");
+}
+
+#[test]
+fn plain_scan_takes_one_cpu_sample_and_does_not_wait() {
+    let runner = FixtureRunner::new().ok("dominfo a", include_str!("fixtures/dominfo_running.txt"));
+    let (mgr, runner) = manager_with_calls(runner);
+
+    let started = Instant::now();
+    let rows = scan::scan_vms(&mgr, &["a".to_string()], 1, false);
+
+    assert!(started.elapsed() < scan::CPU_SAMPLE_GAP, "took {:?}", started.elapsed());
+    assert_eq!(rows[0].cpu_time_seconds, Some(122_705));
+    assert_eq!(rows[0].cpu_percent, None);
+    assert_eq!(runner.calls().iter().filter(|c| c.ends_with("dominfo a")).count(), 1);
+}
+
+const DOMSTATS: &str = "domstats --state --cpu-total --balloon --vcpu --interface --block";
+
+#[test]
+fn domstats_replaces_per_vm_dominfo() {
+    let before = include_str!("fixtures/domstats.txt");
+    let after = before.replace("cpu.time=122705312345678", "cpu.time=122707312345678");
+    let runner = FixtureRunner::new().ok(DOMSTATS, before).ok(DOMSTATS, after);
+    let (mgr, runner) = manager_with_calls(runner);
+    let vms: Vec<String> = vec!["pinhole_new".into(), "fs00".into()];
+
+    let rows = scan::scan_vms_with_cpu(&mgr, &vms, 2);
+
+    assert_eq!(rows[0].memory_used_bytes, Some(Bytes::from_kib(4_194_304)));
+    assert!(rows[0].cpu_percent.unwrap() > 30.0);
+    assert_eq!(rows[1].memory_max_bytes, Some(Bytes::from_kib(2_097_152)));
+    assert_eq!(rows[1].cpu_percent, None);
+    assert_eq!(mgr.get_domstats("pinhole_new").unwrap().net[0].name, "vnet0");
+    let calls = runner.calls();
+    assert_eq!(calls.iter().filter(|c| c.contains("domstats")).count(), 2);
+    assert!(!calls.iter().any(|c| c.contains("dominfo")), "{:?}", calls);
+}
+
+#[test]
+fn falls_back_to_dominfo_once_domstats_fails() {
+    let runner = FixtureRunner::new()
+        .fail(DOMSTATS, "error: unknown command: 'domstats'")
+        .ok("dominfo a", include_str!("fixtures/dominfo_running.txt"));
+    let (mgr, runner) = manager_with_calls(runner);
+
+    let rows = scan::scan_vms(&mgr, &["a".to_string()], 1, false);
+    scan::scan_vms(&mgr, &["a".to_string()], 1, false);
+
+    assert_eq!(rows[0].memory_max_bytes, Some(Bytes::from_kib(8_388_608)));
+    assert_eq!(runner.calls().iter().filter(|c| c.contains("domstats")).count(), 1);
+}
+
+#[test]
+fn domstats_is_retried_after_a_transient_failure() {
+    let runner = FixtureRunner::new()
+        .fail(DOMSTATS, "error: Timed out during operation: cannot acquire state change lock")
+        .ok(DOMSTATS, include_str!("fixtures/domstats.txt"));
+    let (mgr, runner) = manager_with_calls(runner);
+
+    assert!(mgr.sample_domstats().is_err());
+    assert!(mgr.sample_domstats().unwrap().contains_key("pinhole_new"));
+    assert_eq!(runner.calls().iter().filter(|c| c.contains("domstats")).count(), 2);
+}
@@ -0,0 +1,246 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::json;
+
+use dismount_iso_qemu::error::{self, VirshError};
+use dismount_iso_qemu::media;
+use dismount_iso_qemu::probe::{CacheTtls, ProbeManager};
+use dismount_iso_qemu::runner::FixtureRunner;
+use dismount_iso_qemu::scan::{self, StateFilter};
+use dismount_iso_qemu::utils::{Bytes, DomainState};
+use dismount_iso_qemu::virsh::Virsh;
+
+use common::manager_with_calls;
+
+const OSINFO: &str = r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-get-osinfo"}"#;
+const OS: &str = r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-get-os"}"#;
+const INFO: &str = r#"qemu-agent-command --timeout 5 vm1 {"execute":"guest-info"}"#;
+const AGENT_DOWN: &str = "error: Guest agent is not responding: QEMU guest agent is not connected";
+const OSINFO_UNSUPPORTED: &str =
+    "error: internal error: unable to execute QEMU agent command 'guest-get-osinfo': The command guest-get-osinfo has not been found";
+
+fn agent_cmd(payload: serde_json::Value) -> String {
+    format!("qemu-agent-command --timeout 5 vm1 {}", payload)
+}
+
+fn exec_cmd(path: &str, args: &[&str]) -> String {
+    agent_cmd(json!({
+        "execute": "guest-exec",
+        "arguments": { "path": path, "arg": args, "capture-output": true },
+    }))
+}
+
+fn exec_status_cmd(pid: i64) -> String {
+    agent_cmd(json!({ "execute": "guest-exec-status", "arguments": { "pid": pid } }))
+}
+
+#[test]
+fn list_vms_skips_blank_lines_and_passes_uri() {
+    let runner = Arc::new(FixtureRunner::new().ok("list --all --name", include_str!("fixtures/list_all_name.txt")));
+    let virsh = Virsh::with_runner("qemu+ssh://root@host/system", runner.clone());
+
+    assert_eq!(virsh.list_vms().unwrap(), vec!["pinhole_new", "fs00", "win11_lab"]);
+    assert_eq!(runner.calls(), vec!["-c qemu+ssh://root@host/system list --all --name"]);
+    assert!(!virsh.is_local());
+}
+
+#[test]
+fn state_filter_selects_running_or_inactive() {
+    let (mgr, _) = manager_with_calls(FixtureRunner::new().ok("list --all", include_str!("fixtures/list_all.txt")));
+
+    assert_eq!(scan::list_vms(&mgr, Some(StateFilter::Running)).unwrap(), vec!["pinhole_new"]);
+    assert_eq!(scan::list_vms(&mgr, Some(StateFilter::Inactive)).unwrap(), vec!["win11_lab"]);
+    assert_eq!(scan::list_vms(&mgr, None).unwrap().len(), 3);
+    assert_eq!(mgr.domain_state("fs00"), Some(DomainState::Paused));
+}
+
+#[test]
+fn list_vms_reports_virsh_failure() {
+    let runner = Arc::new(FixtureRunner::new().fail("list --all --name", "error: failed to connect to the hypervisor"));
+    let virsh = Virsh::with_runner("qemu:///system", runner);
+
+    let err = virsh.list_vms().unwrap_err();
+    assert!(err.to_string().contains("failed to connect to the hypervisor"));
+}
+
+#[test]
+fn get_os_prefers_osinfo_and_caches() {
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new().ok(OSINFO, include_str!("fixtures/guest_get_osinfo.json")));
+
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("Ubuntu 18.04.6 LTS"));
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("Ubuntu 18.04.6 LTS"));
+    // guest-info (not in the fixtures, so capabilities are unknown) + guest-get-osinfo
+    assert_eq!(runner.calls().len(), 2, "second lookup should be served from cache");
+}
+
+#[test]
+fn get_os_falls_back_to_guest_get_os() {
+    let (mgr, _) = manager_with_calls(
+        FixtureRunner::new()
+            .fail(OSINFO, "error: internal error: unable to execute QEMU agent command 'guest-get-osinfo'")
+            .ok(OS, include_str!("fixtures/guest_get_os.json")),
+    );
+
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("CentOS Linux 7 (Core)"));
+}
+
+#[test]
+fn get_os_skips_rpcs_the_agent_does_not_offer() {
+    let (mgr, runner) = manager_with_calls(
+        FixtureRunner::new()
+            .ok(INFO, include_str!("fixtures/guest_info.json"))
+            .ok(OS, include_str!("fixtures/guest_get_os.json")),
+    );
+
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("CentOS Linux 7 (Core)"));
+    assert_eq!(mgr.get_agent_info("vm1").unwrap().version, "2.5.0");
+    assert!(!runner.calls().iter().any(|c| c.contains("guest-get-osinfo")), "{:?}", runner.calls());
+
+    // guest-exec is disabled, so a failing guest-get-os ends the probe there.
+    let (mgr, runner) = manager_with_calls(
+        FixtureRunner::new()
+            .ok(INFO, include_str!("fixtures/guest_info.json"))
+            .fail(OS, "error: internal error: unable to execute QEMU agent command 'guest-get-os'"),
+    );
+    assert_eq!(mgr.get_os("vm1").unwrap(), None);
+    assert_eq!(runner.calls().len(), 2);
+}
+
+#[test]
+fn get_os_reports_unreachable_agent_and_stops_probing() {
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new().fail(OSINFO, AGENT_DOWN).fail(OS, AGENT_DOWN));
+
+    let err = mgr.get_os("vm1").unwrap_err();
+    assert!(matches!(err.downcast_ref::<VirshError>(), Some(VirshError::AgentNotConnected(_))), "{:?}", err);
+    assert!(error::hint(&err).unwrap().contains("qemu-guest-agent"));
+    assert!(!runner.calls().iter().any(|c| c.ends_with(r#"{"execute":"guest-get-os"}"#)), "{:?}", runner.calls());
+
+    // Cached: unknown for the negative TTL, and no other agent RPC is tried.
+    assert_eq!(mgr.get_os("vm1").unwrap(), None);
+    let calls = runner.calls().len();
+    assert!(mgr.get_agent_info("vm1").is_none());
+    assert_eq!(runner.calls().len(), calls);
+}
+
+#[test]
+fn cdrom_devices_filters_disks() {
+    let runner = Arc::new(
+        FixtureRunner::new().ok("domblklist --details vm1", include_str!("fixtures/domblklist_details.txt")),
+    );
+    let virsh = Virsh::with_runner("qemu:///system", runner);
+
+    let cdroms = media::cdrom_devices(&virsh, "vm1").unwrap();
+    let targets: Vec<&str> = cdroms.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, vec!["sda", "sdb"]);
+}
+
+#[test]
+fn scan_media_reads_cdroms_from_domain_xml() {
+    let runner = Arc::new(
+        FixtureRunner::new()
+            .ok("dumpxml vm1", include_str!("fixtures/dumpxml_q35_efi.xml"))
+            .ok("dumpxml vm2", "<domain"),
+    );
+    let virsh = Virsh::with_runner("qemu:///system", runner);
+
+    let rows = media::scan_media(&virsh, &["vm1".to_string(), "vm2".to_string()]);
+    assert_eq!(rows.len(), 2, "vm2's broken XML is skipped");
+    assert_eq!((rows[0].target.as_str(), rows[0].bus.as_str()), ("sda", "sata"));
+    assert!(rows[0].missing, "the ISO doesn't exist on this machine");
+    assert_eq!(rows[1].source, None);
+    assert!(!rows[1].missing);
+}
+
+#[test]
+fn get_os_falls_back_to_guest_exec_os_release() {
+    let out_data = STANDARD.encode(include_str!("fixtures/os_release_alpine.txt"));
+    let (mgr, _) = manager_with_calls(
+        FixtureRunner::new()
+            .fail(OSINFO, OSINFO_UNSUPPORTED)
+            .fail(OS, "error: internal error: unable to execute QEMU agent command 'guest-get-os'")
+            .ok(&exec_cmd("/bin/cat", &["/etc/os-release"]), r#"{"return":{"pid":812}}"#)
+            .ok(&exec_status_cmd(812), r#"{"return":{"exited":false}}"#)
+            .ok(&exec_status_cmd(812), json!({"return":{"exited":true,"exitcode":0,"out-data":out_data}}).to_string()),
+    );
+
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("Alpine Linux v3.19"));
+}
+
+#[test]
+fn get_os_falls_back_to_windows_ver() {
+    let out_data = STANDARD.encode("\r\nMicrosoft Windows [Version 10.0.22631.3296]\r\n");
+    let (mgr, _) = manager_with_calls(
+        FixtureRunner::new()
+            .fail(OSINFO, OSINFO_UNSUPPORTED)
+            .fail(OS, "error: internal error: unable to execute QEMU agent command 'guest-get-os'")
+            .fail(&exec_cmd("/bin/cat", &["/etc/os-release"]), "error: internal error: Guest agent returned error: CreateProcessW() failed")
+            .ok(&exec_cmd("cmd.exe", &["/c", "ver"]), r#"{"return":{"pid":4}}"#)
+            .ok(&exec_status_cmd(4), json!({"return":{"exited":true,"exitcode":0,"out-data":out_data}}).to_string()),
+    );
+
+    assert_eq!(mgr.get_os("vm1").unwrap().as_deref(), Some("Microsoft Windows [Version 10.0.22631.3296]"));
+}
+
+#[test]
+fn unreachable_agent_is_negatively_cached() {
+    let (mgr, runner) = manager_with_calls(FixtureRunner::new().fail(OSINFO, AGENT_DOWN).fail(OS, AGENT_DOWN));
+
+    assert!(mgr.get_os("vm1").is_err());
+    let probes = runner.calls().len();
+    assert_eq!(mgr.get_os("vm1").unwrap(), None);
+    assert_eq!(runner.calls().len(), probes, "dead guest should not be re-probed within the negative TTL");
+}
+
+#[test]
+fn negative_entries_expire_before_positive_ones() {
+    let runner = Arc::new(FixtureRunner::new().fail(OSINFO, AGENT_DOWN).fail(OS, AGENT_DOWN));
+    let virsh = Virsh::with_runner("qemu:///system", runner.clone());
+    let ttls = CacheTtls { negative: Duration::ZERO, ..CacheTtls::default() };
+    let mgr = ProbeManager::with_virsh(virsh, Duration::from_secs(5), ttls).unwrap();
+
+    let _ = mgr.get_os("vm1");
+    let probes = runner.calls().len();
+    let _ = mgr.get_os("vm1");
+    assert_eq!(runner.calls().len(), probes * 2);
+}
+
+#[test]
+fn dominfo_is_cached_and_failures_are_remembered() {
+    let (mgr, runner) = manager_with_calls(
+        FixtureRunner::new()
+            .ok("dominfo vm1", include_str!("fixtures/dominfo_running.txt"))
+            .fail("dominfo gone", "error: failed to get domain 'gone'"),
+    );
+
+    let info = mgr.get_dominfo("vm1").unwrap().unwrap();
+    assert_eq!(info.used_memory, Some(Bytes::from_kib(8_388_608)));
+    mgr.get_dominfo("vm1").unwrap();
+
+    assert!(mgr.get_dominfo("gone").is_err());
+    assert!(mgr.get_dominfo("gone").unwrap().is_none());
+
+    assert_eq!(runner.calls().len(), 2);
+}
+
+#[test]
+fn interfaces_prefer_agent_then_leases_then_arp() {
+    let net = agent_cmd(json!({ "execute": "guest-network-get-interfaces" }));
+    let (mgr, _) = manager_with_calls(FixtureRunner::new().ok(&net, include_str!("fixtures/guest_network_interfaces.json")));
+    assert_eq!(mgr.get_interfaces("vm1").unwrap()[0].name, "enp1s0");
+
+    let (mgr, runner) = manager_with_calls(
+        FixtureRunner::new()
+            .fail(&net, AGENT_DOWN)
+            .ok("domifaddr vm1 --source lease", "")
+            .ok("domifaddr vm1 --source arp", include_str!("fixtures/domifaddr_arp.txt")),
+    );
+    let ifaces = mgr.get_interfaces("vm1").unwrap();
+    assert_eq!(ifaces[0].name, "vnet0");
+    mgr.get_interfaces("vm1");
+    assert_eq!(runner.calls().len(), 4, "second lookup must come from the cache");
+}
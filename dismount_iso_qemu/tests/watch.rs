@@ -0,0 +1,68 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use dismount_iso_qemu::runner::FixtureRunner;
+use dismount_iso_qemu::scan::VmStatus;
+use dismount_iso_qemu::utils::{Bytes, DomInfo};
+use dismount_iso_qemu::watch;
+
+use common::manager;
+
+fn row(name: &str, used_kib: u64, cpu: &str) -> VmStatus {
+    let info = DomInfo {
+        max_memory: Some(Bytes::from_kib(8388608)),
+        used_memory: Some(Bytes::from_kib(used_kib)),
+        cpu_time: Some(cpu.into()),
+        vcpus: Some(2),
+    };
+    VmStatus::new(name, Ok(Some("Debian 12".into())), &info)
+}
+
+#[test]
+fn frame_shows_deltas_against_previous_refresh() {
+    let prev: HashMap<String, VmStatus> =
+        [row("web01", 2097152, "100.0s")].into_iter().map(|r| (r.name.clone(), r)).collect();
+    let rows = vec![row("web01", 1048576, "190.0s"), row("new01", 1048576, "5.0s")];
+
+    let mut out = Vec::new();
+    watch::render_frame(&rows, &prev, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap().replace("\x1b[K", "");
+    let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split_whitespace().collect()).collect();
+
+    // web01: memory shrank by 1 GiB, CPU advanced 90 s.
+    assert!(lines[1].ends_with(&["-1.0", "GiB", "3m", "10s", "+1m", "30s", "-"]), "{:?}", lines[1]);
+    // new01 has no previous sample.
+    assert_eq!(lines[2][lines[2].len() - 2], "-");
+    assert_eq!(lines[2][lines[2].len() - 4], "-");
+}
+
+/// A terminal that went away: every write fails like a closed pipe.
+struct ClosedPipe;
+
+impl Write for ClosedPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn watch_returns_once_output_fails() {
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mgr = manager(FixtureRunner::new());
+        let result = watch::watch(&mgr, Duration::from_millis(10), 1, None, &mut ClosedPipe);
+        let _ = done_tx.send(result.map_err(|e| e.kind()));
+    });
+
+    let result = done_rx.recv_timeout(Duration::from_secs(5)).expect("watch kept running after stdout closed");
+    assert_eq!(result, Err(io::ErrorKind::BrokenPipe));
+}